@@ -1,15 +1,84 @@
 //! Abstract syntax tree types for letpl.
 
+use std::cell::{Cell, RefCell};
+
 use crate::types::Type;
 
 /// A program node in an AST.
+#[derive(Debug)]
 pub struct Program {
-    /// The program's expression.
-    pub expr: Box<Expr>,
+    /// The libraries the program imports, in the order they are imported.
+    pub imports: Vec<Import>,
+
+    /// The program's module declarations, in the order they are declared.
+    pub modules: Vec<Module>,
+
+    /// The program's class declarations, in the order they are declared.
+    pub classes: Vec<Class>,
+
+    /// The program's top-level definitions, in order.
+    pub defs: Vec<Def>,
+
+    /// The program's main expression, if it has one.
+    pub expr: Option<Box<Expr>>,
+}
+
+/// A library, a file of declarations which a program can import.
+pub struct Library {
+    pub imports: Vec<Import>,
+    pub modules: Vec<Module>,
+    pub classes: Vec<Class>,
+    pub defs: Vec<Def>,
+}
+
+/// A top-level definition of a global, which is in scope in the definitions
+/// after it and in the program's main expression.
+#[derive(Clone, Debug)]
+pub enum Def {
+    /// A name bound to the value of an expression by `def`.
+    Value { name: String, expr: Expr },
+
+    /// Procedures defined together by `defrec`, which may call themselves and
+    /// each other.
+    Rec(Vec<RecProc>),
+}
+
+impl Def {
+    /// Tests if this definition contains a `set` of a given name.
+    pub fn assigns(&self, name: &str) -> bool {
+        match self {
+            Def::Value { expr, .. } => expr.assigns(name),
+            Def::Rec(procs) => procs.iter().any(|proc| proc.body.assigns(name)),
+        }
+    }
+}
+
+/// Tests if a name is assigned by any of a sequence of definitions or by the
+/// main expression following them.
+pub fn is_assigned_by(defs: &[Def], expr: Option<&Expr>, name: &str) -> bool {
+    defs.iter().any(|def| def.assigns(name)) || expr.is_some_and(|expr| expr.assigns(name))
+}
+
+/// A library imported by a program or another library.
+#[derive(Debug)]
+pub enum Import {
+    /// A library file given by its path, relative to the importing file.
+    Path(String),
+
+    /// A library given by name, found in the search path.
+    Name(String),
 }
 
 /// An expression node in an AST.
+#[derive(Clone, Debug)]
 pub enum Expr {
+    /// An expression that adds left and right.
+    Add { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that is `true` if both left and right are `true`. Right is
+    /// only evaluated if left is `true`.
+    And { left: Box<Expr>, right: Box<Expr> },
+
     /// An expression guarded by a test expression.
     Assert {
         line: usize,
@@ -17,8 +86,66 @@ pub enum Expr {
         body: Box<Expr>,
     },
 
+    /// An expression that assigns a new value to a variable.
+    Assign { name: String, expr: Box<Expr> },
+
+    /// An expression that evaluates a sequence of expressions, giving the
+    /// value of the last.
+    Begin(Vec<Expr>),
+
     /// A procedure call expression.
-    Call { proc: Box<Expr>, arg: Box<Expr> },
+    Call { proc: Box<Expr>, args: Vec<Expr> },
+
+    /// An expression that calls a procedure with the current continuation.
+    CallCc(Box<Expr>),
+
+    /// An expression that gives the first element of a list.
+    Car(Box<Expr>),
+
+    /// An expression that gives a list without its first element.
+    Cdr(Box<Expr>),
+
+    /// An expression that joins two strings.
+    Concat { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that makes a list from a first element and a list of the
+    /// rest.
+    Cons { head: Box<Expr>, tail: Box<Expr> },
+
+    /// A variant type declaration. The type and its constructors are in scope
+    /// in the body.
+    Data {
+        name: String,
+        variants: Vec<Variant>,
+        body: Box<Expr>,
+    },
+
+    /// An expression that gives the value a reference refers to.
+    DeRef(Box<Expr>),
+
+    /// An expression that divides left by right, truncating toward zero.
+    Divide { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that gives a field of a record.
+    Field {
+        record: Box<Expr>,
+        name: String,
+        /// The field's offset in the record, filled in by the type checker
+        /// which knows the record's layout.
+        offset: Cell<usize>,
+    },
+
+    /// The empty list.
+    EmptyList,
+
+    /// An expression that tests if left and right are equal.
+    Equal { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that tests if left is greater than right.
+    Greater { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that tests if left is greater than or equal to right.
+    GreaterEqual { left: Box<Expr>, right: Box<Expr> },
 
     /// A conditional expression.
     If {
@@ -27,9 +154,24 @@ pub enum Expr {
         alternate: Box<Expr>,
     },
 
+    /// An expression that converts an integer to its decimal string.
+    IntToString(Box<Expr>),
+
+    /// An expression that tests if a list is empty.
+    IsNull(Box<Expr>),
+
     /// An expression that test if a sub-expression is zero.
     IsZero(Box<Expr>),
 
+    /// An expression that gives the number of characters in a string.
+    Length(Box<Expr>),
+
+    /// An expression that tests if left is less than right.
+    Less { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that tests if left is less than or equal to right.
+    LessEqual { left: Box<Expr>, right: Box<Expr> },
+
     /// An expression with a name bound to a value.
     Let {
         name: String,
@@ -37,54 +179,398 @@ pub enum Expr {
         body: Box<Expr>,
     },
 
-    /// A recursive procedure definition expression.
+    /// An expression with a name bound to the current continuation.
+    LetCc { name: String, body: Box<Expr> },
+
+    /// An expression defining procedures which may call themselves and each
+    /// other.
     LetRec {
-        t_result: Type,
-        name: String,
-        param: Param,
-        proc_body: Box<Expr>,
-        let_body: Box<Expr>,
+        procs: Vec<RecProc>,
+        body: Box<Expr>,
     },
 
+    /// A list construction expression.
+    List(Vec<Expr>),
+
     /// A literal Boolean expression.
     LiteralBool(bool),
 
     /// A literal integer expression.
     LiteralInt(i64),
 
+    /// A literal string expression.
+    LiteralString(String),
+
+    /// An expression that picks an arm by the constructor of a variant value.
+    Match { expr: Box<Expr>, arms: Vec<Arm> },
+
+    /// An expression that multiplies left and right.
+    Multiply { left: Box<Expr>, right: Box<Expr> },
+
     /// A name lookup expression.
     Name(String),
 
     /// An expression that negates its inner expression.
     Negate(Box<Expr>),
 
+    /// An expression that makes a new, unlocked mutex.
+    NewMutex,
+
+    /// An expression that makes an object of a class and sends it the
+    /// `initialize` method with some arguments.
+    NewObject { class: String, args: Vec<Expr> },
+
+    /// An expression that allocates a reference to the value of its inner
+    /// expression.
+    NewRef(Box<Expr>),
+
+    /// An expression that gives the Boolean negation of its inner expression.
+    Not(Box<Expr>),
+
+    /// An expression that tests if left and right are not equal.
+    NotEqual { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that is `true` if either left or right is `true`. Right is
+    /// only evaluated if left is `false`.
+    Or { left: Box<Expr>, right: Box<Expr> },
+
     /// A procedure definition expression.
-    Proc { param: Param, body: Box<Expr> },
+    Proc { params: Vec<Param>, body: Box<Expr> },
+
+    /// An expression that raises an exception with its inner expression's
+    /// value.
+    Raise(Box<Expr>),
+
+    /// A record construction expression.
+    Record(Vec<(String, Expr)>),
+
+    /// An expression that gives the remainder of dividing left by right.
+    Remainder { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that gives a value a module's interface declares.
+    Qualified { module: String, name: String },
+
+    /// An expression that changes the value a reference refers to.
+    SetRef {
+        reference: Box<Expr>,
+        value: Box<Expr>,
+    },
+
+    /// An expression that converts a decimal string to an integer.
+    StringToInt(Box<Expr>),
 
     /// An expression that subtracts right from left.
     Subtract { left: Box<Expr>, right: Box<Expr> },
+
+    /// An expression that gives the object a method was sent to.
+    SelfObject,
+
+    /// An expression that calls an object's method, as found in the object's
+    /// class.
+    Send {
+        object: Box<Expr>,
+        method: String,
+        args: Vec<Expr>,
+    },
+
+    /// An expression that unlocks a mutex, or passes it to the first thread
+    /// waiting on it.
+    Signal(Box<Expr>),
+
+    /// An expression that starts a thread which calls a procedure.
+    Spawn(Box<Expr>),
+
+    /// An expression that gives the characters of a string from start up to
+    /// but not including end.
+    Substring {
+        string: Box<Expr>,
+        start: Box<Expr>,
+        end: Box<Expr>,
+    },
+
+    /// An expression that resumes a continuation with a value.
+    Throw { value: Box<Expr>, cont: Box<Expr> },
+
+    /// An expression whose body's exceptions are handled by binding their
+    /// values to a name in a handler expression.
+    Try {
+        body: Box<Expr>,
+        name: String,
+        handler: Box<Expr>,
+    },
+
+    /// An expression that calls a method of the current class's superclass on
+    /// the object the current method was sent to.
+    Super { method: String, args: Vec<Expr> },
+
+    /// A tuple construction expression.
+    Tuple(Vec<Expr>),
+
+    /// An expression that gives a copy of a record with some fields changed.
+    Update {
+        record: Box<Expr>,
+        fields: Vec<(String, Expr)>,
+        /// The changed fields' offsets in the record, filled in by the type
+        /// checker which knows the record's layout.
+        offsets: RefCell<Vec<usize>>,
+    },
+
+    /// An expression with names bound to the elements of a tuple.
+    Unpack {
+        names: Vec<String>,
+        expr: Box<Expr>,
+        body: Box<Expr>,
+    },
+
+    /// An expression that locks a mutex, waiting until it is unlocked if
+    /// another thread holds it.
+    Wait(Box<Expr>),
+
+    /// An expression that lets the other threads run.
+    Yield,
 }
 
+impl Expr {
+    /// Gives the expressions directly inside this expression.
+    pub fn sub_exprs(&self) -> Vec<&Expr> {
+        match self {
+            Expr::EmptyList
+            | Expr::NewMutex
+            | Expr::SelfObject
+            | Expr::Yield
+            | Expr::LiteralBool(_)
+            | Expr::LiteralInt(_)
+            | Expr::LiteralString(_)
+            | Expr::Name(_)
+            | Expr::Qualified { .. } => Vec::new(),
+            Expr::Assign { expr, .. }
+            | Expr::CallCc(expr)
+            | Expr::Car(expr)
+            | Expr::Cdr(expr)
+            | Expr::DeRef(expr)
+            | Expr::Field { record: expr, .. }
+            | Expr::IntToString(expr)
+            | Expr::IsNull(expr)
+            | Expr::Length(expr)
+            | Expr::StringToInt(expr)
+            | Expr::IsZero(expr)
+            | Expr::Negate(expr)
+            | Expr::NewRef(expr)
+            | Expr::Not(expr)
+            | Expr::Signal(expr)
+            | Expr::Spawn(expr)
+            | Expr::Wait(expr)
+            | Expr::Raise(expr) => vec![expr],
+            Expr::Add { left, right }
+            | Expr::And { left, right }
+            | Expr::Concat { left, right }
+            | Expr::Cons {
+                head: left,
+                tail: right,
+            }
+            | Expr::Divide { left, right }
+            | Expr::Equal { left, right }
+            | Expr::Greater { left, right }
+            | Expr::GreaterEqual { left, right }
+            | Expr::Less { left, right }
+            | Expr::LessEqual { left, right }
+            | Expr::Multiply { left, right }
+            | Expr::NotEqual { left, right }
+            | Expr::Or { left, right }
+            | Expr::Remainder { left, right }
+            | Expr::Subtract { left, right }
+            | Expr::Throw {
+                value: left,
+                cont: right,
+            }
+            | Expr::SetRef {
+                reference: left,
+                value: right,
+            } => vec![left, right],
+            Expr::Assert { test, body, .. } => vec![test, body],
+            Expr::Begin(exprs) | Expr::List(exprs) | Expr::Tuple(exprs) => exprs.iter().collect(),
+            Expr::Call { proc, args } => {
+                let mut exprs = vec![proc.as_ref()];
+                exprs.extend(args);
+                exprs
+            }
+            Expr::If {
+                test,
+                consequent,
+                alternate,
+            }
+            | Expr::Substring {
+                string: test,
+                start: consequent,
+                end: alternate,
+            } => vec![test, consequent, alternate],
+            Expr::Let { expr, body, .. } | Expr::Unpack { expr, body, .. } => vec![expr, body],
+            Expr::NewObject { args, .. } | Expr::Super { args, .. } => args.iter().collect(),
+            Expr::Send { object, args, .. } => {
+                let mut exprs = vec![object.as_ref()];
+                exprs.extend(args);
+                exprs
+            }
+            Expr::Try { body, handler, .. } => vec![body, handler],
+            Expr::LetRec { procs, body } => {
+                let mut exprs: Vec<&Expr> = procs.iter().map(|proc| &proc.body).collect();
+                exprs.push(body);
+                exprs
+            }
+            Expr::Proc { body, .. } | Expr::Data { body, .. } | Expr::LetCc { body, .. } => {
+                vec![body]
+            }
+            Expr::Record(fields) => fields.iter().map(|(_, expr)| expr).collect(),
+            Expr::Update { record, fields, .. } => {
+                let mut exprs = vec![record.as_ref()];
+                exprs.extend(fields.iter().map(|(_, expr)| expr));
+                exprs
+            }
+            Expr::Match { expr, arms } => {
+                let mut exprs = vec![expr.as_ref()];
+                exprs.extend(arms.iter().map(|arm| &arm.body));
+                exprs
+            }
+        }
+    }
+
+    /// Tests if this expression contains a `set` of a given name. Shadowing is
+    /// ignored, so the test is conservative.
+    pub fn assigns(&self, name: &str) -> bool {
+        if let Expr::Assign {
+            name: assigned_name,
+            ..
+        } = self
+        {
+            if assigned_name == name {
+                return true;
+            }
+        }
+        self.sub_exprs()
+            .into_iter()
+            .any(|sub_expr| sub_expr.assigns(name))
+    }
+}
+
+/// A procedure parameter, with a type if one was given.
+#[derive(Clone, Debug)]
 pub struct Param {
     pub name: String,
-    pub t: Type,
+    pub t: Option<Type>,
 }
 
 impl Param {
-    pub fn new(name: String, t: Type) -> Param {
+    pub fn new(name: String, t: Option<Type>) -> Param {
         Param { name, t }
     }
 }
 
+/// A procedure defined by `letrec` or `defrec`, with a result type if one was
+/// given.
+#[derive(Clone, Debug)]
+pub struct RecProc {
+    pub t_result: Option<Type>,
+    pub name: String,
+    pub params: Vec<Param>,
+    pub body: Expr,
+}
+
+/// A module declaration. Only the values and types its interface declares
+/// can be used outside its body.
+#[derive(Clone, Debug)]
+pub struct Module {
+    pub name: String,
+    pub interface: Vec<Declaration>,
+    pub body: Vec<Definition>,
+}
+
+/// A declaration in a module's interface.
+#[derive(Clone, Debug)]
+pub enum Declaration {
+    /// A type whose definition is hidden outside the module's body.
+    Opaque(String),
+
+    /// A type whose definition is known outside the module's body.
+    Transparent { name: String, t: Type },
+
+    /// A value of a given type.
+    Value { name: String, t: Type },
+}
+
+/// A definition in a module's body, which is in scope in the definitions after
+/// it.
+#[derive(Clone, Debug)]
+pub enum Definition {
+    /// A name for a type.
+    Type { name: String, t: Type },
+
+    /// A name bound to the value of an expression.
+    Value { name: String, expr: Expr },
+}
+
+/// A class declaration. Objects of the class have the superclass's fields
+/// followed by the class's own, and the superclass's methods unless the class
+/// overrides them.
+#[derive(Clone, Debug)]
+pub struct Class {
+    pub name: String,
+    pub superclass: String,
+    pub fields: Vec<(String, Type)>,
+    pub methods: Vec<Method>,
+}
+
+/// A method declaration in a class.
+#[derive(Clone, Debug)]
+pub struct Method {
+    pub name: String,
+    pub t_result: Type,
+    pub params: Vec<Param>,
+    pub body: Expr,
+}
+
+/// A constructor of a variant type and the types of its fields.
+#[derive(Clone, Debug)]
+pub struct Variant {
+    pub name: String,
+    pub t_fields: Vec<Type>,
+}
+
+/// An arm of a `match` expression, which binds names to the fields of a value
+/// made by its constructor.
+#[derive(Clone, Debug)]
+pub struct Arm {
+    pub constructor: String,
+    pub names: Vec<String>,
+    pub body: Expr,
+}
+
 pub mod nameless {
     //! A namless version of the AST, that is, an AST without identifiers.
     use crate::offset::{Capture, CaptureOffset, StackOffset};
 
+    #[derive(Debug)]
     pub struct Program {
         pub expr: Box<Expr>,
+
+        /// The number of global slots once the program's definitions are
+        /// made, including those of the programs run before it.
+        pub global_count: usize,
     }
 
+    #[derive(Debug)]
     pub enum Expr {
+        /// An expression that adds left and right.
+        Add {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
+        /// An expression that is `true` if both left and right are `true`.
+        And {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
         /// An expression which guards its body expression by a test expression.
         Assert {
             line: usize,
@@ -92,16 +578,90 @@ pub mod nameless {
             body: Box<Expr>,
         },
 
+        /// An expression that evaluates a sequence of expressions, giving the
+        /// value of the last.
+        Begin(Vec<Expr>),
+
         /// A procedure call expression.
         Call {
             proc: Box<Expr>,
-            arg: Box<Expr>,
+            args: Vec<Expr>,
         },
 
+        /// An expression that calls a procedure with the current
+        /// continuation.
+        CallCc(Box<Expr>),
+
         Capture(CaptureOffset),
 
+        /// An expression that makes a class from its superclass, if it has
+        /// one other than `object`, and its methods' procedures.
+        Class {
+            name: String,
+            superclass: Option<Box<Expr>>,
+            /// The names of the fields of the class's objects, including
+            /// inherited fields, in the order of their offsets.
+            fields: Vec<String>,
+            methods: Vec<(String, Expr)>,
+        },
+
+        /// An expression that gives the first element of a list.
+        Car(Box<Expr>),
+
+        /// An expression that gives a list without its first element.
+        Cdr(Box<Expr>),
+
+        /// An expression that joins two strings.
+        Concat {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
+        /// An expression that makes a list from a first element and a list of
+        /// the rest.
+        Cons {
+            head: Box<Expr>,
+            tail: Box<Expr>,
+        },
+
+        /// An expression that gives the value a reference refers to.
+        DeRef(Box<Expr>),
+
+        /// An expression that divides left by right, truncating toward zero.
+        Divide {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
+        /// The empty list.
+        EmptyList,
+
+        /// An expression that tests if left and right are equal.
+        Equal {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
+        /// An expression that gives the field at an offset in a record.
+        Field {
+            record: Box<Expr>,
+            offset: usize,
+        },
+
         Global(StackOffset),
 
+        /// An expression that tests if left is greater than right.
+        Greater {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
+        /// An expression that tests if left is greater than or equal to right.
+        GreaterEqual {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
         /// A conditional expression.
         If {
             test: Box<Expr>,
@@ -109,32 +669,243 @@ pub mod nameless {
             alternate: Box<Expr>,
         },
 
+        /// An expression that converts an integer to its decimal string.
+        IntToString(Box<Expr>),
+
+        /// An expression that tests if a list is empty.
+        IsNull(Box<Expr>),
+
         IsZero(Box<Expr>),
 
+        /// An expression that gives the number of characters in a string.
+        Length(Box<Expr>),
+
+        /// An expression that tests if left is less than right.
+        Less {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
+        /// An expression that tests if left is less than or equal to right.
+        LessEqual {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
         Let {
             expr: Box<Expr>,
             body: Box<Expr>,
         },
 
+        /// A list construction expression.
+        List(Vec<Expr>),
+
+        /// An expression that binds the current continuation to a stack slot.
+        LetCc {
+            /// The continuation's slot, if it must be boxed.
+            boxed: Option<StackOffset>,
+            body: Box<Expr>,
+        },
+
         LiteralBool(bool),
 
         /// A literal integer expression.
         LiteralInt(i64),
 
+        /// A literal string expression.
+        LiteralString(String),
+
         Local(StackOffset),
 
+        /// An expression that picks an arm by the tag of a variant value.
+        Match {
+            expr: Box<Expr>,
+            arms: Vec<Arm>,
+        },
+
+        /// An expression that multiplies left and right.
+        Multiply {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
         /// An expression that negates its inner expression.
         Negate(Box<Expr>),
 
+        /// An expression that makes a new, unlocked mutex.
+        NewMutex,
+
+        /// An expression that makes an object of a class and sends it the
+        /// `initialize` method, if its class has one, with some arguments.
+        NewObject {
+            class: Box<Expr>,
+            args: Vec<Expr>,
+        },
+
+        /// An expression that gives the field of an object at an offset.
+        ObjectField {
+            object: Box<Expr>,
+            offset: usize,
+        },
+
+        /// An expression that allocates a reference to the value of its inner
+        /// expression.
+        NewRef(Box<Expr>),
+
+        /// An expression that gives the Boolean negation of its inner
+        /// expression.
+        Not(Box<Expr>),
+
+        /// An expression that tests if left and right are not equal.
+        NotEqual {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
+        /// An expression that is `true` if either left or right is `true`.
+        Or {
+            left: Box<Expr>,
+            right: Box<Expr>,
+        },
+
         Proc {
             body: Box<Expr>,
             captures: Vec<Capture>,
+            /// The parameters which must be boxed when the procedure is called.
+            boxed_params: Vec<StackOffset>,
+        },
+
+        /// An expression that raises an exception with its inner expression's
+        /// value.
+        Raise(Box<Expr>),
+
+        /// A record construction expression.
+        Record(Vec<(String, Expr)>),
+
+        /// An expression that gives the remainder of dividing left by right.
+        Remainder {
+            left: Box<Expr>,
+            right: Box<Expr>,
         },
 
+        /// An expression that changes the value a reference refers to.
+        SetRef {
+            reference: Box<Expr>,
+            value: Box<Expr>,
+        },
+
+        /// An expression that converts a decimal string to an integer.
+        StringToInt(Box<Expr>),
+
         /// An expression that subtracts right from left.
         Subtract {
             left: Box<Expr>,
             right: Box<Expr>,
         },
+
+        /// An expression that calls an object's method, as found in the
+        /// object's class.
+        Send {
+            object: Box<Expr>,
+            method: String,
+            args: Vec<Expr>,
+        },
+
+        /// An expression that assigns a value to the field of an object at an
+        /// offset.
+        SetObjectField {
+            object: Box<Expr>,
+            offset: usize,
+            value: Box<Expr>,
+        },
+
+        /// An expression that unlocks a mutex, or passes it to the first
+        /// thread waiting on it.
+        Signal(Box<Expr>),
+
+        /// An expression that starts a thread which calls a procedure.
+        Spawn(Box<Expr>),
+
+        /// An expression that gives the characters of a string from start up
+        /// to but not including end.
+        Substring {
+            string: Box<Expr>,
+            start: Box<Expr>,
+            end: Box<Expr>,
+        },
+
+        /// An expression that resumes a continuation with a value.
+        Throw {
+            value: Box<Expr>,
+            cont: Box<Expr>,
+        },
+
+        /// An expression whose body's exceptions are handled by a handler
+        /// expression, with the exception's value in the stack slot where the
+        /// body's value would have been.
+        Try {
+            body: Box<Expr>,
+            /// The exception's slot, if it must be boxed.
+            boxed: Option<StackOffset>,
+            handler: Box<Expr>,
+        },
+
+        /// An expression that calls a method, as found in a given class, on
+        /// an object.
+        SuperSend {
+            object: Box<Expr>,
+            args: Vec<Expr>,
+            class: Box<Expr>,
+            method: String,
+        },
+
+        /// A tuple construction expression.
+        Tuple(Vec<Expr>),
+
+        /// The unit value, `()`.
+        Unit,
+
+        /// An expression that gives a copy of a record with the fields at some
+        /// offsets changed.
+        Update {
+            record: Box<Expr>,
+            offsets: Vec<usize>,
+            values: Vec<Expr>,
+        },
+
+        /// An expression which binds the elements of a tuple to consecutive
+        /// stack slots.
+        Unpack {
+            expr: Box<Expr>,
+            count: usize,
+            /// The slots which must be boxed after unpacking.
+            boxed: Vec<StackOffset>,
+            body: Box<Expr>,
+        },
+
+        /// An expression that makes a variant value from its fields.
+        Variant {
+            name: String,
+            tag: usize,
+            fields: Vec<Expr>,
+        },
+
+        /// An expression that locks a mutex, waiting until it is unlocked if
+        /// another thread holds it.
+        Wait(Box<Expr>),
+
+        /// An expression that lets the other threads run.
+        Yield,
+    }
+
+    /// An arm of a `match` expression, which binds the fields of a variant
+    /// value with its tag to consecutive stack slots.
+    #[derive(Debug)]
+    pub struct Arm {
+        pub tag: usize,
+        pub count: usize,
+        /// The slots which must be boxed after unpacking.
+        pub boxed: Vec<StackOffset>,
+        pub body: Expr,
     }
 }
@@ -1,9 +1,11 @@
 //! A bytecode compiler for letpl.
 
 use std::fmt;
+use std::mem;
+use std::rc::Rc;
 
 use crate::ast::nameless::{Expr, Program};
-use crate::runtime::{Address, Op, Value};
+use crate::runtime::{Address, List, Op, Value};
 
 #[derive(Copy, Clone, PartialEq)]
 enum ExprPos {
@@ -21,12 +23,32 @@ struct Chunk {
     pub ops: Vec<Op>,
 }
 
-pub fn compile(program: &Program) -> Result<Vec<Op>, String> {
-    let mut chunk = Chunk::new();
-    compile_expr(&program.expr, Scope::Global, ExprPos::Tail, &mut chunk)?;
-    Ok(chunk.ops)
+/// Compiles a program after the ops of the programs run before it, giving the
+/// address of its first op. The program halts at its end, rather than running
+/// on into the ops of a later program.
+pub fn compile(program: &Program, ops: &mut Vec<Op>) -> Result<Address, String> {
+    let mut chunk = Chunk::new(mem::take(ops));
+    let start = chunk.next_address();
+    let result = compile_program(program, &mut chunk);
+    *ops = chunk.ops;
+    result.map(|()| start)
 }
 
+/// Gives a listing of the ops a program compiles to, as if it were the first
+/// program run.
+pub fn disassemble(program: &Program) -> Result<String, String> {
+    let mut chunk = Chunk::new(Vec::new());
+    compile_program(program, &mut chunk)?;
+    Ok(format!("{chunk:?}"))
+}
+
+fn compile_program(program: &Program, chunk: &mut Chunk) -> Result<(), String> {
+    let result = compile_expr(&program.expr, Scope::Global, ExprPos::Tail, chunk);
+    chunk.emit(Op::Halt);
+    result
+}
+
+#[allow(clippy::too_many_lines)]
 fn compile_expr(
     expr: &Expr,
     scope: Scope,
@@ -37,20 +59,32 @@ fn compile_expr(
         Expr::Assert { line, test, body } => {
             compile_expr(test, scope, ExprPos::Operand, chunk)?;
             chunk.emit(Op::Assert { line: *line });
-            compile_expr(body, scope, ExprPos::Tail, chunk)?;
+            compile_expr(body, scope, expr_pos, chunk)?;
+        }
+
+        Expr::Begin(exprs) => {
+            let (last, init) = exprs.split_last().expect("begin has an expression");
+            for expr in init {
+                compile_expr(expr, scope, ExprPos::Operand, chunk)?;
+                chunk.emit(Op::Pop);
+            }
+            compile_expr(last, scope, expr_pos, chunk)?;
         }
 
         Expr::Capture(capture_offset) => {
             chunk.emit(Op::PushCapture(*capture_offset));
         }
 
-        Expr::Call { proc, arg } => {
+        Expr::Call { proc, args } => {
             compile_expr(proc, scope, ExprPos::Operand, chunk)?;
-            compile_expr(arg, scope, ExprPos::Operand, chunk)?;
+            for arg in args {
+                compile_expr(arg, scope, ExprPos::Operand, chunk)?;
+            }
+            let arity = args.len();
             if scope == Scope::Local && expr_pos == ExprPos::Tail {
-                chunk.emit(Op::TailCall);
+                chunk.emit(Op::TailCall(arity));
             } else {
-                chunk.emit(Op::Call);
+                chunk.emit(Op::Call(arity));
             }
         }
 
@@ -59,10 +93,358 @@ fn compile_expr(
             chunk.emit(Op::PushValue(v));
         }
 
+        Expr::LiteralString(s) => {
+            let v = Value::String(Rc::from(s.as_str()));
+            chunk.emit(Op::PushValue(v));
+        }
+
+        Expr::Concat { left, right } => {
+            compile_binary(left, right, Op::Concat, scope, chunk)?;
+        }
+
+        Expr::Length(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Length);
+        }
+
+        Expr::Substring { string, start, end } => {
+            compile_expr(string, scope, ExprPos::Operand, chunk)?;
+            compile_expr(start, scope, ExprPos::Operand, chunk)?;
+            compile_expr(end, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Substring);
+        }
+
+        Expr::IntToString(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::IntToString);
+        }
+
+        Expr::StringToInt(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::StringToInt);
+        }
+
+        Expr::Add { left, right } => {
+            compile_binary(left, right, Op::Add, scope, chunk)?;
+        }
+
         Expr::Subtract { left, right } => {
+            compile_binary(left, right, Op::Diff, scope, chunk)?;
+        }
+
+        Expr::Multiply { left, right } => {
+            compile_binary(left, right, Op::Multiply, scope, chunk)?;
+        }
+
+        Expr::Divide { left, right } => {
+            compile_binary(left, right, Op::Divide, scope, chunk)?;
+        }
+
+        Expr::Remainder { left, right } => {
+            compile_binary(left, right, Op::Remainder, scope, chunk)?;
+        }
+
+        Expr::And { left, right } => {
+            compile_expr(left, scope, ExprPos::Operand, chunk)?;
+            let branch_to_right = chunk.emit(Op::JumpTrue(Address(0)));
+            chunk.emit(Op::PushValue(Value::Boolean(false)));
+            let branch_to_end = chunk.emit(Op::Jump(Address(0)));
+            let right_start = chunk.next_address();
+            compile_expr(right, scope, expr_pos, chunk)?;
+            let and_end = chunk.next_address();
+            chunk.patch(branch_to_right, right_start);
+            chunk.patch(branch_to_end, and_end);
+        }
+
+        Expr::Or { left, right } => {
             compile_expr(left, scope, ExprPos::Operand, chunk)?;
-            compile_expr(right, scope, ExprPos::Operand, chunk)?;
-            chunk.emit(Op::Diff);
+            let branch_to_true = chunk.emit(Op::JumpTrue(Address(0)));
+            compile_expr(right, scope, expr_pos, chunk)?;
+            let branch_to_end = chunk.emit(Op::Jump(Address(0)));
+            let true_start = chunk.emit(Op::PushValue(Value::Boolean(true)));
+            let or_end = chunk.next_address();
+            chunk.patch(branch_to_true, true_start);
+            chunk.patch(branch_to_end, or_end);
+        }
+
+        Expr::NewRef(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::NewRef);
+        }
+
+        Expr::EmptyList => {
+            chunk.emit(Op::PushValue(Value::List(List::empty())));
+        }
+
+        Expr::Cons { head, tail } => {
+            compile_binary(head, tail, Op::Cons, scope, chunk)?;
+        }
+
+        Expr::Car(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Car);
+        }
+
+        Expr::Cdr(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Cdr);
+        }
+
+        Expr::IsNull(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::IsNull);
+        }
+
+        Expr::List(exprs) => {
+            for expr in exprs {
+                compile_expr(expr, scope, ExprPos::Operand, chunk)?;
+            }
+            chunk.emit(Op::MakeList(exprs.len()));
+        }
+
+        Expr::Record(fields) => {
+            for (_, expr) in fields {
+                compile_expr(expr, scope, ExprPos::Operand, chunk)?;
+            }
+            let names = fields
+                .iter()
+                .map(|(name, _)| Rc::from(name.as_str()))
+                .collect();
+            chunk.emit(Op::MakeRecord(names));
+        }
+
+        Expr::Field { record, offset } => {
+            compile_expr(record, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::GetField(*offset));
+        }
+
+        Expr::Update {
+            record,
+            offsets,
+            values,
+        } => {
+            compile_expr(record, scope, ExprPos::Operand, chunk)?;
+            for value in values {
+                compile_expr(value, scope, ExprPos::Operand, chunk)?;
+            }
+            chunk.emit(Op::UpdateRecord(offsets.clone()));
+        }
+
+        Expr::LetCc { boxed, body } => {
+            let make_cont = chunk.emit(Op::MakeCont(Address(0)));
+            if let Some(stack_offset) = boxed {
+                chunk.emit(Op::BoxLocal(*stack_offset));
+            }
+            compile_expr(body, scope, expr_pos, chunk)?;
+            end_scope(1, expr_pos, chunk);
+            let letcc_end = chunk.next_address();
+            chunk.patch(make_cont, letcc_end);
+        }
+
+        Expr::CallCc(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::CallCc);
+        }
+
+        Expr::Throw { value, cont } => {
+            compile_binary(value, cont, Op::Throw, scope, chunk)?;
+        }
+
+        Expr::Class {
+            name,
+            superclass,
+            fields,
+            methods,
+        } => {
+            if let Some(superclass) = superclass {
+                compile_expr(superclass, scope, ExprPos::Operand, chunk)?;
+            }
+            for (_, method) in methods {
+                compile_expr(method, scope, ExprPos::Operand, chunk)?;
+            }
+            chunk.emit(Op::MakeClass {
+                name: Rc::from(name.as_str()),
+                fields: fields
+                    .iter()
+                    .map(|field_name| Rc::from(field_name.as_str()))
+                    .collect(),
+                methods: methods
+                    .iter()
+                    .map(|(method_name, _)| Rc::from(method_name.as_str()))
+                    .collect(),
+                has_superclass: superclass.is_some(),
+            });
+        }
+
+        Expr::NewObject { class, args } => {
+            compile_expr(class, scope, ExprPos::Operand, chunk)?;
+            for arg in args {
+                compile_expr(arg, scope, ExprPos::Operand, chunk)?;
+            }
+            chunk.emit(Op::NewObject(args.len()));
+            // Discard `initialize`'s value, leaving the object.
+            chunk.emit(Op::Pop);
+        }
+
+        Expr::ObjectField { object, offset } => {
+            compile_expr(object, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::GetObjectField(*offset));
+        }
+
+        Expr::SetObjectField {
+            object,
+            offset,
+            value,
+        } => {
+            compile_expr(object, scope, ExprPos::Operand, chunk)?;
+            compile_expr(value, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::SetObjectField(*offset));
+        }
+
+        Expr::Send {
+            object,
+            method,
+            args,
+        } => {
+            compile_expr(object, scope, ExprPos::Operand, chunk)?;
+            for arg in args {
+                compile_expr(arg, scope, ExprPos::Operand, chunk)?;
+            }
+            chunk.emit(Op::Send {
+                method: Rc::from(method.as_str()),
+                arity: args.len(),
+            });
+        }
+
+        Expr::SuperSend {
+            object,
+            args,
+            class,
+            method,
+        } => {
+            compile_expr(object, scope, ExprPos::Operand, chunk)?;
+            for arg in args {
+                compile_expr(arg, scope, ExprPos::Operand, chunk)?;
+            }
+            compile_expr(class, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::SuperSend {
+                method: Rc::from(method.as_str()),
+                arity: args.len(),
+            });
+        }
+
+        Expr::Spawn(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Spawn);
+        }
+
+        Expr::Yield => {
+            chunk.emit(Op::Yield);
+        }
+
+        Expr::Unit => {
+            chunk.emit(Op::PushValue(Value::Unit));
+        }
+
+        Expr::NewMutex => {
+            chunk.emit(Op::NewMutex);
+        }
+
+        Expr::Wait(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Wait);
+        }
+
+        Expr::Signal(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Signal);
+        }
+
+        Expr::Raise(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Raise);
+        }
+
+        Expr::Try {
+            body,
+            boxed,
+            handler,
+        } => {
+            // The body is never in tail position, as its handler must stay on
+            // the call stack until the body is done.
+            let push_handler = chunk.emit(Op::PushHandler(Address(0)));
+            compile_expr(body, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::PopHandler);
+            let branch_to_end = chunk.emit(Op::Jump(Address(0)));
+            let handler_start = chunk.next_address();
+            if let Some(stack_offset) = boxed {
+                chunk.emit(Op::BoxLocal(*stack_offset));
+            }
+            compile_expr(handler, scope, expr_pos, chunk)?;
+            end_scope(1, expr_pos, chunk);
+            let try_end = chunk.next_address();
+            chunk.patch(push_handler, handler_start);
+            chunk.patch(branch_to_end, try_end);
+        }
+
+        Expr::Tuple(exprs) => {
+            for expr in exprs {
+                compile_expr(expr, scope, ExprPos::Operand, chunk)?;
+            }
+            chunk.emit(Op::MakeTuple(exprs.len()));
+        }
+
+        Expr::Unpack {
+            expr,
+            count,
+            boxed,
+            body,
+        } => {
+            compile_expr(expr, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Unpack(*count));
+            for stack_offset in boxed {
+                chunk.emit(Op::BoxLocal(*stack_offset));
+            }
+            compile_expr(body, scope, expr_pos, chunk)?;
+            end_scope(*count, expr_pos, chunk);
+        }
+
+        Expr::DeRef(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::DeRef);
+        }
+
+        Expr::SetRef { reference, value } => {
+            compile_binary(reference, value, Op::SetRef, scope, chunk)?;
+        }
+
+        Expr::Not(e) => {
+            compile_expr(e, scope, ExprPos::Operand, chunk)?;
+            chunk.emit(Op::Not);
+        }
+
+        Expr::Equal { left, right } => {
+            compile_binary(left, right, Op::Equal, scope, chunk)?;
+        }
+
+        Expr::NotEqual { left, right } => {
+            compile_binary(left, right, Op::NotEqual, scope, chunk)?;
+        }
+
+        Expr::Less { left, right } => {
+            compile_binary(left, right, Op::Less, scope, chunk)?;
+        }
+
+        Expr::LessEqual { left, right } => {
+            compile_binary(left, right, Op::LessEqual, scope, chunk)?;
+        }
+
+        Expr::Greater { left, right } => {
+            compile_binary(left, right, Op::Greater, scope, chunk)?;
+        }
+
+        Expr::GreaterEqual { left, right } => {
+            compile_binary(left, right, Op::GreaterEqual, scope, chunk)?;
         }
 
         Expr::Negate(expr) => {
@@ -81,10 +463,10 @@ fn compile_expr(
         } => {
             compile_expr(test, scope, ExprPos::Operand, chunk)?;
             let branch_to_consq = chunk.emit(Op::JumpTrue(Address(0)));
-            compile_expr(alternate, scope, ExprPos::Tail, chunk)?;
+            compile_expr(alternate, scope, expr_pos, chunk)?;
             let branch_to_end = chunk.emit(Op::Jump(Address(0)));
             let consq_start = chunk.next_address();
-            compile_expr(consequent, scope, ExprPos::Tail, chunk)?;
+            compile_expr(consequent, scope, expr_pos, chunk)?;
             let if_end = chunk.next_address();
             chunk.patch(branch_to_consq, consq_start);
             chunk.patch(branch_to_end, if_end);
@@ -97,7 +479,47 @@ fn compile_expr(
 
         Expr::Let { expr, body } => {
             compile_expr(expr, scope, ExprPos::Operand, chunk)?;
-            compile_expr(body, scope, ExprPos::Tail, chunk)?;
+            compile_expr(body, scope, expr_pos, chunk)?;
+            end_scope(1, expr_pos, chunk);
+        }
+
+        Expr::Match { expr, arms } => {
+            compile_expr(expr, scope, ExprPos::Operand, chunk)?;
+            let branches_to_arms: Vec<Address> = arms
+                .iter()
+                .map(|arm| {
+                    chunk.emit(Op::TestTag(arm.tag));
+                    chunk.emit(Op::JumpTrue(Address(0)))
+                })
+                .collect();
+            chunk.emit(Op::NoMatch);
+            let mut branches_to_end = Vec::new();
+            for (arm, branch_to_arm) in arms.iter().zip(branches_to_arms) {
+                let arm_start = chunk.next_address();
+                chunk.patch(branch_to_arm, arm_start);
+                chunk.emit(Op::UnpackVariant);
+                for stack_offset in &arm.boxed {
+                    chunk.emit(Op::BoxLocal(*stack_offset));
+                }
+                compile_expr(&arm.body, scope, expr_pos, chunk)?;
+                end_scope(arm.count, expr_pos, chunk);
+                branches_to_end.push(chunk.emit(Op::Jump(Address(0))));
+            }
+            let match_end = chunk.next_address();
+            for branch_to_end in branches_to_end {
+                chunk.patch(branch_to_end, match_end);
+            }
+        }
+
+        Expr::Variant { name, tag, fields } => {
+            for field in fields {
+                compile_expr(field, scope, ExprPos::Operand, chunk)?;
+            }
+            chunk.emit(Op::MakeVariant {
+                name: Rc::from(name.as_str()),
+                tag: *tag,
+                count: fields.len(),
+            });
         }
 
         Expr::LiteralBool(value) => {
@@ -108,9 +530,16 @@ fn compile_expr(
             chunk.emit(Op::PushLocal(*stack_offset));
         }
 
-        Expr::Proc { body, captures } => {
+        Expr::Proc {
+            body,
+            captures,
+            boxed_params,
+        } => {
             let branch_make_proc = chunk.emit(Op::Jump(Address(0)));
             let start = chunk.next_address();
+            for stack_offset in boxed_params {
+                chunk.emit(Op::BoxLocal(*stack_offset));
+            }
             compile_expr(body, Scope::Local, ExprPos::Tail, chunk)?;
             chunk.emit(Op::Return);
             let captures = captures.clone();
@@ -122,9 +551,30 @@ fn compile_expr(
     Ok(())
 }
 
+/// Discards the values bound by a scope, keeping the scope's value on top of
+/// the stack. A scope in tail position needs nothing discarded, as its frame
+/// is discarded when the procedure returns.
+fn end_scope(count: usize, expr_pos: ExprPos, chunk: &mut Chunk) {
+    if count > 0 && expr_pos == ExprPos::Operand {
+        chunk.emit(Op::PopBelow(count));
+    }
+}
+
+fn compile_binary(
+    left: &Expr,
+    right: &Expr,
+    op: Op,
+    scope: Scope,
+    chunk: &mut Chunk,
+) -> Result<(), String> {
+    compile_expr(left, scope, ExprPos::Operand, chunk)?;
+    compile_expr(right, scope, ExprPos::Operand, chunk)?;
+    chunk.emit(op);
+    Ok(())
+}
+
 impl Chunk {
-    fn new() -> Self {
-        let ops = Vec::new();
+    fn new(ops: Vec<Op>) -> Self {
         Chunk { ops }
     }
 
@@ -146,6 +596,12 @@ impl Chunk {
             Op::JumpTrue(_) => {
                 self.ops[patch_at] = Op::JumpTrue(target);
             }
+            Op::MakeCont(_) => {
+                self.ops[patch_at] = Op::MakeCont(target);
+            }
+            Op::PushHandler(_) => {
+                self.ops[patch_at] = Op::PushHandler(target);
+            }
             _ => (),
         }
     }
@@ -0,0 +1,173 @@
+//! Loads the libraries a letpl program imports.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Class, Def, Import, Module, Program};
+use crate::parser;
+
+/// The extension of letpl source files, which is left out of an import by
+/// name.
+const EXTENSION: &str = "let";
+
+/// Loads the libraries a program imports, directly or through other libraries,
+/// and adds their declarations to the program before its own. A library
+/// imported more than once is only loaded once. The program's file, if it has
+/// one, is the start of every import chain and its directory is where imports
+/// by path are found.
+pub fn load_imports(
+    program: &mut Program,
+    file: Option<&Path>,
+    search_path: &[PathBuf],
+) -> Result<(), String> {
+    let mut loader = Loader {
+        search_path,
+        chain: Vec::new(),
+        loaded: Vec::new(),
+        defined: Vec::new(),
+        modules: Vec::new(),
+        classes: Vec::new(),
+        defs: Vec::new(),
+    };
+    let dir = match file {
+        Some(file) => {
+            let file = canonicalize(file)?;
+            let dir = directory_of(&file);
+            loader.chain.push(file);
+            dir
+        }
+        None => PathBuf::from("."),
+    };
+    for import in &program.imports {
+        loader.import(import, &dir)?;
+    }
+
+    loader.modules.append(&mut program.modules);
+    loader.classes.append(&mut program.classes);
+    loader.defs.append(&mut program.defs);
+    program.modules = loader.modules;
+    program.classes = loader.classes;
+    program.defs = loader.defs;
+    program.imports.clear();
+    Ok(())
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf, String> {
+    fs::canonicalize(path).map_err(|e| format!("cannot open `{}`: {e}", path.display()))
+}
+
+fn directory_of(file: &Path) -> PathBuf {
+    file.parent()
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+}
+
+/// The state of loading a program's imports.
+struct Loader<'a> {
+    /// The directories searched for libraries imported by name, in order.
+    search_path: &'a [PathBuf],
+
+    /// The files being loaded, each imported by the one before it.
+    chain: Vec<PathBuf>,
+
+    /// The files which have been loaded.
+    loaded: Vec<PathBuf>,
+
+    /// The names defined by the loaded libraries, each with the file which
+    /// defines it.
+    defined: Vec<(String, PathBuf)>,
+
+    /// The declarations of the loaded libraries, in the order they must be
+    /// declared.
+    modules: Vec<Module>,
+    classes: Vec<Class>,
+    defs: Vec<Def>,
+}
+
+impl Loader<'_> {
+    /// Loads an imported library after the libraries it imports. Imports by
+    /// path are relative to a given directory.
+    fn import(&mut self, import: &Import, dir: &Path) -> Result<(), String> {
+        let file = match import {
+            Import::Path(path) => canonicalize(&dir.join(path)),
+            Import::Name(name) => self.find(name),
+        }
+        .map_err(|e| self.describe_error(&e))?;
+        if self.chain.contains(&file) {
+            self.chain.push(file);
+            return Err(format!("import cycle: {}", self.describe_chain()));
+        }
+        if self.loaded.contains(&file) {
+            return Ok(());
+        }
+
+        self.chain.push(file.clone());
+        let library = fs::read_to_string(&file)
+            .map_err(|e| format!("cannot read `{}`: {e}", file.display()))
+            .and_then(|src| parser::parse_library(&src))
+            .map_err(|e| self.describe_error(&e))?;
+        let dir = directory_of(&file);
+        for import in &library.imports {
+            self.import(import, &dir)?;
+        }
+        self.define(&library.defs, &file)?;
+        self.chain.pop();
+
+        self.loaded.push(file);
+        self.modules.extend(library.modules);
+        self.classes.extend(library.classes);
+        self.defs.extend(library.defs);
+        Ok(())
+    }
+
+    /// Records the names a library defines. Every library's definitions are
+    /// in scope in the libraries loaded after it, so a library may not define
+    /// a name another library defines, which could hide the name from the
+    /// libraries using it.
+    fn define(&mut self, defs: &[Def], file: &Path) -> Result<(), String> {
+        let names = defs.iter().flat_map(|def| match def {
+            Def::Value { name, .. } => vec![name],
+            Def::Rec(procs) => procs.iter().map(|proc| &proc.name).collect(),
+        });
+        for name in names {
+            match self.defined.iter().find(|(defined, _)| defined == name) {
+                Some((_, other)) if other != file => {
+                    let msg = format!("`{name}` is also defined by `{}`", other.display());
+                    return Err(self.describe_error(&msg));
+                }
+                Some(_) => {}
+                None => self.defined.push((name.clone(), file.to_path_buf())),
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds a library imported by name in the first directory of the search
+    /// path which has it.
+    fn find(&self, name: &str) -> Result<PathBuf, String> {
+        let file_name = Path::new(name).with_extension(EXTENSION);
+        self.search_path
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|file| file.is_file())
+            .ok_or_else(|| format!("cannot find library `{name}` in the search path"))
+            .and_then(|file| canonicalize(&file))
+    }
+
+    /// Describes an error found while loading the last file in the import
+    /// chain.
+    fn describe_error(&self, msg: &str) -> String {
+        if self.chain.is_empty() {
+            msg.to_string()
+        } else {
+            format!("{msg} (import chain: {})", self.describe_chain())
+        }
+    }
+
+    fn describe_chain(&self) -> String {
+        self.chain
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+}
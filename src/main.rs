@@ -2,73 +2,127 @@
 
 mod ast;
 mod compiler;
+mod loader;
 mod name_analysis;
 mod offset;
 mod parser;
+mod prelude;
+mod repl;
 mod runtime;
 mod scanner;
+mod session;
 mod table;
 mod type_checking;
 mod types;
 
 use std::error::Error;
-use std::io::Write;
-use std::{env, fs, io};
+use std::path::{Path, PathBuf};
+use std::{env, fs};
 
+use ast::Program;
+use prelude::Prelude;
 use runtime::Value;
+use session::{Session, Timings};
 use types::Type;
 
 type EvalResult = Result<(Value, Type), Box<dyn Error>>;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    match args.len() {
-        1 => repl(),
-        2 => run_file(&args[1]),
-        _ => println!("Usage: letpl [script]"),
-    }
+/// Settings given on the command line.
+struct Options {
+    script: Option<String>,
+
+    /// The number of ops a thread runs before another thread is run.
+    quantum: usize,
+
+    /// The directories searched for libraries imported by name, those given
+    /// with `--path` followed by those in `LETPL_PATH`.
+    search_path: Vec<PathBuf>,
+
+    /// Whether the prelude is in scope, unless `--no-prelude` is given.
+    use_prelude: bool,
 }
 
-fn run_file(path: &str) {
-    let result = read_file_eval(path);
-    print(result);
+fn main() {
+    let Some(options) = parse_args(env::args().skip(1)) else {
+        println!("Usage: letpl [--quantum n] [--path dirs] [--no-prelude] [script]");
+        return;
+    };
+    let prelude = if options.use_prelude {
+        match Prelude::load() {
+            Ok(prelude) => prelude,
+            Err(e) => {
+                eprintln!("error: {e}");
+                return;
+            }
+        }
+    } else {
+        Prelude::empty()
+    };
+    let session = match Session::new(&prelude, options.quantum) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return;
+        }
+    };
+    match &options.script {
+        Some(path) => run_file(Path::new(path), &options, session),
+        None => repl::repl(&options, &prelude, session),
+    }
 }
 
-fn repl() -> ! {
-    loop {
-        print!("> ");
-        let result = read_eval();
-        print(result);
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Options> {
+    let mut options = Options {
+        script: None,
+        quantum: runtime::DEFAULT_QUANTUM,
+        search_path: Vec::new(),
+        use_prelude: true,
+    };
+    while let Some(arg) = args.next() {
+        if arg == "--quantum" {
+            options.quantum = args.next()?.parse().ok().filter(|&n| n > 0)?;
+        } else if arg == "--no-prelude" {
+            options.use_prelude = false;
+        } else if arg == "--path" {
+            options.search_path.extend(env::split_paths(&args.next()?));
+        } else if options.script.is_none() {
+            options.script = Some(arg);
+        } else {
+            return None;
+        }
+    }
+    if let Some(paths) = env::var_os("LETPL_PATH") {
+        options.search_path.extend(env::split_paths(&paths));
     }
+    Some(options)
 }
 
-fn read_file_eval(path: &str) -> EvalResult {
-    let src = fs::read_to_string(path)?;
-    let t = eval(&src)?;
-    Ok(t)
+fn run_file(path: &Path, options: &Options, mut session: Session) {
+    let result = read_file_eval(path, options, &mut session);
+    print(result);
 }
 
-fn read_eval() -> EvalResult {
-    let src = read()?;
-    let t = eval(&src)?;
-    Ok(t)
+fn read_file_eval(path: &Path, options: &Options, session: &mut Session) -> EvalResult {
+    let src = fs::read_to_string(path)?;
+    let mut program = parser::parse(&src)?;
+    loader::load_imports(&mut program, Some(path), &options.search_path)?;
+    eval(&program, session, &mut Timings::new())
 }
 
-fn read() -> Result<String, Box<dyn Error>> {
-    // Must flush or the prompt never gets printed.
-    io::stdout().flush()?;
-    let mut buffer = String::new();
-    let _ = io::stdin().read_line(&mut buffer)?;
-    Ok(buffer)
+/// Evaluates a program whose imports have been loaded, in which the
+/// declarations of the programs evaluated before it in the session are in
+/// scope.
+fn eval(program: &Program, session: &mut Session, timings: &mut Timings) -> EvalResult {
+    let mut warnings = Vec::new();
+    let result = session.eval(program, timings, &mut warnings);
+    print_warnings(warnings);
+    Ok(result?)
 }
 
-fn eval(src: &str) -> EvalResult {
-    let program = parser::parse(src)?;
-    let program_type = type_checking::type_of_program(&program)?;
-    let nameless_program = name_analysis::resolve_names(&program)?;
-    let compiled_program = compiler::compile(&nameless_program)?;
-    let value = runtime::run(&compiled_program)?;
-    Ok((value, program_type))
+fn print_warnings(warnings: Vec<String>) {
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
 }
 
 fn print(result: EvalResult) {
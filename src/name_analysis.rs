@@ -5,60 +5,148 @@ use crate::ast::nameless;
 use crate::offset::{Capture, CaptureOffset, StackOffset};
 use crate::table::Table;
 
-fn lookup<'a, T: Clone>(bindings: &'a Option<Table<T>>, name: &str) -> Option<&'a T> {
+fn lookup<'a, T: Clone>(bindings: Option<&'a Table<T>>, name: &str) -> Option<&'a T> {
     match bindings {
         Some(bindings) => bindings.lookup(name),
         None => None,
     }
 }
 
-struct CaptureTable(Table<Capture>);
+/// How a name's value is kept. A name which is assigned with `set` keeps its
+/// value in a box, so every closure capturing the name shares the box rather
+/// than a copy of the value.
+#[derive(Clone, Copy, PartialEq)]
+enum Storage {
+    /// The value itself, as the name is never assigned.
+    Value,
+
+    /// A box holding the value, which `set` can assign.
+    Box,
+
+    /// A box holding a procedure defined together with others by `letrec` or
+    /// `defrec`, so they can refer to each other. It cannot be assigned.
+    SharedBox,
+}
+
+impl Storage {
+    fn of(is_boxed: bool) -> Self {
+        if is_boxed {
+            Storage::Box
+        } else {
+            Storage::Value
+        }
+    }
+
+    fn is_boxed(self) -> bool {
+        self != Storage::Value
+    }
+}
+
+/// Where a name's value is kept on the stack.
+#[derive(Clone, Copy)]
+struct Binding {
+    stack_offset: StackOffset,
+    storage: Storage,
+}
+
+/// A name captured by a procedure.
+#[derive(Clone, Copy)]
+struct Captured {
+    capture: Capture,
+    storage: Storage,
+}
+
+#[derive(Clone)]
+struct CaptureTable(Table<Captured>);
 
 impl CaptureTable {
     fn new() -> Self {
         CaptureTable(Table::new())
     }
 
-    fn add_local_capture(&mut self, name: String, stack_offset: StackOffset) -> CaptureOffset {
-        let capture = Capture::Local(stack_offset);
-        self.push(name, capture)
+    fn add_local_capture(&mut self, name: String, binding: Binding) -> CaptureOffset {
+        let captured = Captured {
+            capture: Capture::Local(binding.stack_offset),
+            storage: binding.storage,
+        };
+        self.push(name, captured)
     }
 
     fn add_capture_capture(
         &mut self,
         name: String,
         outer_capture_offset: CaptureOffset,
+        storage: Storage,
     ) -> CaptureOffset {
-        let capture = Capture::Capture(outer_capture_offset);
-        self.push(name, capture)
+        let captured = Captured {
+            capture: Capture::Capture(outer_capture_offset),
+            storage,
+        };
+        self.push(name, captured)
     }
 
-    pub fn lookup(&self, name: &str) -> Option<CaptureOffset> {
+    pub fn lookup(&self, name: &str) -> Option<(CaptureOffset, Storage)> {
         let CaptureTable(table) = self;
         table
             .lookup_offset(name)
-            .map(|offset| CaptureOffset(offset))
+            .map(|offset| (CaptureOffset(offset), table.items[offset].value.storage))
     }
 
-    pub fn push(&mut self, name: String, capture: Capture) -> CaptureOffset {
+    pub fn push(&mut self, name: String, captured: Captured) -> CaptureOffset {
         let CaptureTable(table) = self;
-        table.push(name, capture);
+        table.push(name, captured);
         CaptureOffset(table.len() - 1)
     }
 }
 
+#[derive(Clone)]
 struct Frame {
     stack_top: StackOffset,
-    locals: Option<Table<StackOffset>>,
+    locals: Option<Table<Binding>>,
     captures: CaptureTable,
 }
 
+/// Where a class's value is kept and the names of its objects' fields,
+/// including inherited ones, in order.
+#[derive(Clone)]
+struct ClassLayout {
+    slot: StackOffset,
+    fields: Vec<String>,
+}
+
+/// Where a name's value is found.
+enum Location {
+    /// A stack slot or capture, and how the value is kept in it.
+    Value(Box<nameless::Expr>, Storage),
+
+    /// A field of the object a method was sent to, given by the expression
+    /// for the object and the field's offset.
+    Field(Box<nameless::Expr>, usize),
+}
+
+#[derive(Clone)]
 struct StackState {
     stack_top: StackOffset,
     save_stack: Vec<StackOffset>,
-    globals: Table<StackOffset>,
-    locals: Option<Table<StackOffset>>,
+    globals: Table<Binding>,
+    locals: Option<Table<Binding>>,
     call_stack: Vec<Frame>,
+
+    /// The tag of each constructor in scope.
+    constructors: Table<usize>,
+
+    /// The names and bindings of the values each module's body defines.
+    modules: Table<Vec<(String, Binding)>>,
+
+    /// The layout of each class.
+    classes: Table<ClassLayout>,
+
+    /// The fields in scope in the method being analysed.
+    fields: Vec<String>,
+
+    /// The slot of the superclass of the method being analysed, unless it is
+    /// `object`.
+    superclass: Option<StackOffset>,
 }
 
 impl StackState {
@@ -69,10 +157,15 @@ impl StackState {
             globals: Table::new(),
             locals: None,
             call_stack: Vec::new(),
+            constructors: Table::new(),
+            modules: Table::new(),
+            classes: Table::new(),
+            fields: Vec::new(),
+            superclass: None,
         }
     }
 
-    fn current_bindings(&mut self) -> &mut Table<StackOffset> {
+    fn current_bindings(&mut self) -> &mut Table<Binding> {
         match self.locals.as_mut() {
             Some(locals) => locals,
             None => &mut self.globals,
@@ -95,18 +188,44 @@ impl StackState {
         self.stack_top = self.save_stack.pop().expect("save stack underflow");
     }
 
-    fn begin_scope(&mut self, name: &str) {
-        let stack_offset = self.stack_top - StackOffset(1);
-        self.current_bindings().push(name.to_string(), stack_offset);
+    /// Begins a scope binding names, given with whether they are boxed, to the
+    /// top stack slots in order.
+    fn begin_scope(&mut self, names: &[(&str, bool)]) {
+        let mut stack_offset = self.stack_top - StackOffset(names.len());
+        for (name, is_boxed) in names {
+            let binding = Binding {
+                stack_offset,
+                storage: Storage::of(*is_boxed),
+            };
+            self.current_bindings().push((*name).to_string(), binding);
+            stack_offset += StackOffset(1);
+        }
     }
 
-    fn end_scope(&mut self) {
-        self.current_bindings().pop();
+    /// Begins a scope binding the name of a procedure defined together with
+    /// others to the box in the top stack slot.
+    fn begin_shared_scope(&mut self, name: &str) {
+        let binding = Binding {
+            stack_offset: self.stack_top - StackOffset(1),
+            storage: Storage::SharedBox,
+        };
+        self.current_bindings().push(name.to_string(), binding);
+    }
+
+    /// Ends a scope which bound a given number of names. The scope's value
+    /// replaces the names' slots on the stack.
+    fn end_scope(&mut self, count: usize) {
+        for _ in 0..count {
+            self.current_bindings().pop();
+        }
+        self.stack_top -= StackOffset(count);
     }
 
-    fn begin_proc(&mut self, proc_name: &str, param_name: &str) {
+    /// Begins a procedure whose parameters are given by name and whether they
+    /// are boxed.
+    fn begin_proc(&mut self, proc_name: &str, params: &[(&str, bool)]) {
         let stack_top = std::mem::replace(&mut self.stack_top, StackOffset(0));
-        let locals = std::mem::replace(&mut self.locals, Some(Table::new()));
+        let locals = self.locals.replace(Table::new());
         let frame = Frame {
             stack_top,
             locals,
@@ -114,11 +233,13 @@ impl StackState {
         };
         self.call_stack.push(frame);
 
-        // simulate pushing proc object and argument
+        // simulate pushing proc object and arguments
         self.push();
-        self.begin_scope(proc_name);
-        self.push();
-        self.begin_scope(param_name);
+        self.begin_scope(&[(proc_name, false)]);
+        for _ in params {
+            self.push();
+        }
+        self.begin_scope(params);
     }
 
     fn end_proc(&mut self) -> CaptureTable {
@@ -128,11 +249,11 @@ impl StackState {
         frame.captures
     }
 
-    fn lookup_local(&mut self, name: &str) -> Option<&StackOffset> {
-        lookup(&self.locals, name)
+    fn lookup_local(&mut self, name: &str) -> Option<&Binding> {
+        lookup(self.locals.as_ref(), name)
     }
 
-    fn lookup_capture(&mut self, name: &str) -> Option<CaptureOffset> {
+    fn lookup_capture(&mut self, name: &str) -> Option<(CaptureOffset, Storage)> {
         let call_depth = self.call_stack.len();
         if call_depth > 0 {
             self.capture(name, call_depth - 1)
@@ -141,34 +262,219 @@ impl StackState {
         }
     }
 
-    fn capture(&mut self, name: &str, call_depth: usize) -> Option<CaptureOffset> {
+    fn capture(&mut self, name: &str, call_depth: usize) -> Option<(CaptureOffset, Storage)> {
         let frame = &mut self.call_stack[call_depth];
-        if let Some(stack_offset) = lookup(&frame.locals, name) {
-            let capture_offset = frame
-                .captures
-                .add_local_capture(name.to_string(), *stack_offset);
-            Some(capture_offset)
-        } else if let Some(capture_offset) = frame.captures.lookup(name) {
-            Some(capture_offset)
+        if let Some(binding) = lookup(frame.locals.as_ref(), name) {
+            let binding = *binding;
+            let capture_offset = frame.captures.add_local_capture(name.to_string(), binding);
+            Some((capture_offset, binding.storage))
+        } else if let Some(captured) = frame.captures.lookup(name) {
+            Some(captured)
         } else if call_depth > 0 {
             self.capture(name, call_depth - 1)
-                .map(|outer_capture_offset| {
-                    self.call_stack[call_depth]
-                        .captures
-                        .add_capture_capture(name.to_string(), outer_capture_offset)
+                .map(|(outer_capture_offset, storage)| {
+                    let capture_offset = self.call_stack[call_depth].captures.add_capture_capture(
+                        name.to_string(),
+                        outer_capture_offset,
+                        storage,
+                    );
+                    (capture_offset, storage)
                 })
         } else {
-            None
+            // A procedure made at global scope captures the globals it uses
+            // rather than reading their slots when called, as the slot of a
+            // name bound inside an expression is reused once its scope ends.
+            let binding = *self.globals.lookup(name)?;
+            let capture_offset = self.call_stack[0]
+                .captures
+                .add_local_capture(name.to_string(), binding);
+            Some((capture_offset, binding.storage))
         }
     }
 }
 
-pub fn resolve_names(program: &ast::Program) -> Result<nameless::Program, String> {
-    let mut state = StackState::new();
-    let expr = resolve_names_expr(&program.expr, &mut state)?;
-    Ok(nameless::Program { expr })
+/// The globals in scope at the start of a program, kept so a program can use
+/// the globals of the programs run before it in the same session.
+#[derive(Clone)]
+pub struct Globals(StackState);
+
+impl Globals {
+    pub fn new() -> Self {
+        Globals(StackState::new())
+    }
 }
 
+/// Resolves the names in a program, in which the globals of the programs run
+/// before it are in scope, and adds the program's own globals to them.
+pub fn resolve_names(
+    program: &ast::Program,
+    globals: &mut Globals,
+) -> Result<nameless::Program, String> {
+    let Globals(state) = globals;
+    let mut definitions = Vec::new();
+
+    // Each module's values are kept in global slots in the order they are
+    // defined, and go out of scope at the end of the module's body.
+    for module in &program.modules {
+        let mut count = 0;
+        for (i, definition) in module.body.iter().enumerate() {
+            let ast::Definition::Value { name, expr } = definition else {
+                continue;
+            };
+            let is_boxed = module.body[i + 1..].iter().any(
+                |later| matches!(later, ast::Definition::Value { expr, .. } if expr.assigns(name)),
+            );
+            definitions.push(*resolve_names_definition(name, expr, is_boxed, state)?);
+            count += 1;
+        }
+        let values = state.globals.items[state.globals.len() - count..]
+            .iter()
+            .map(|item| (item.name.clone(), item.value))
+            .collect();
+        for _ in 0..count {
+            state.globals.pop();
+        }
+        state.modules.push(module.name.clone(), values);
+    }
+
+    // Every class is laid out before any method is analysed, as a method can
+    // refer to any class.
+    for (i, class) in program.classes.iter().enumerate() {
+        let mut fields = if class.superclass == "object" {
+            Vec::new()
+        } else {
+            let Some(superclass) = state.classes.lookup(&class.superclass) else {
+                return Err(format!("undefined class `{}`", class.superclass));
+            };
+            superclass.fields.clone()
+        };
+        fields.extend(class.fields.iter().map(|(name, _)| name.clone()));
+        let slot = state.stack_top + StackOffset(i);
+        state
+            .classes
+            .push(class.name.clone(), ClassLayout { slot, fields });
+    }
+    for class in &program.classes {
+        definitions.push(*resolve_names_class(class, state)?);
+        state.push();
+    }
+
+    let main_expr = program.expr.as_deref();
+    resolve_names_defs(&program.defs, &mut definitions, state)?;
+    let StackOffset(global_count) = state.stack_top;
+    let mut expr = if let Some(expr) = main_expr {
+        let expr = resolve_names_expr(expr, state)?;
+        // The main expression's value is not kept as a global.
+        state.pop();
+        expr
+    } else {
+        Box::new(nameless::Expr::Unit)
+    };
+    for definition in definitions.into_iter().rev() {
+        expr = Box::new(nameless::Expr::Let {
+            expr: Box::new(definition),
+            body: expr,
+        });
+    }
+    Ok(nameless::Program { expr, global_count })
+}
+
+/// Resolves the names in top-level definitions. Each global's value is added
+/// to the definitions in order.
+fn resolve_names_defs(
+    defs: &[ast::Def],
+    definitions: &mut Vec<nameless::Expr>,
+    state: &mut StackState,
+) -> Result<(), String> {
+    for def in defs {
+        match def {
+            ast::Def::Value {
+                name,
+                expr: def_expr,
+            } => {
+                // A later program in the same session may assign the name, so
+                // it is always boxed.
+                definitions.push(*resolve_names_definition(name, def_expr, true, state)?);
+            }
+            ast::Def::Rec(procs) => {
+                if let [proc] = procs.as_slice() {
+                    let expr = resolve_names_proc(&proc.name, &proc.params, &proc.body, state)?;
+                    state.begin_scope(&[(&proc.name, false)]);
+                    definitions.push(*expr);
+                } else {
+                    let (boxes, exprs) = resolve_names_rec_group(procs, state)?;
+                    definitions.extend(boxes);
+                    // The procedures are set in a global of their own, which
+                    // has no name.
+                    definitions.push(nameless::Expr::Begin(exprs));
+                    state.push();
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Resolves the names in a definition of a global, which is in scope from then
+/// on.
+fn resolve_names_definition(
+    name: &str,
+    expr: &ast::Expr,
+    is_boxed: bool,
+    state: &mut StackState,
+) -> Result<Box<nameless::Expr>, String> {
+    let expr = resolve_names_expr(expr, state)?;
+    let expr = if is_boxed {
+        Box::new(nameless::Expr::NewRef(expr))
+    } else {
+        expr
+    };
+    state.begin_scope(&[(name, is_boxed)]);
+    Ok(expr)
+}
+
+fn resolve_names_class(
+    class: &ast::Class,
+    state: &mut StackState,
+) -> Result<Box<nameless::Expr>, String> {
+    let layout = state
+        .classes
+        .lookup(&class.name)
+        .expect("every class is laid out");
+    let fields = layout.fields.clone();
+    let field_names = fields.clone();
+    let superclass = state
+        .classes
+        .lookup(&class.superclass)
+        .map(|superclass| superclass.slot);
+
+    state.fields = fields;
+    state.superclass = superclass;
+    let mut methods = Vec::new();
+    for method in &class.methods {
+        let mut params = vec![("self", false)];
+        params.extend(
+            method
+                .params
+                .iter()
+                .map(|param| (param.name.as_str(), method.body.assigns(&param.name))),
+        );
+        let proc = resolve_names_proc_params("", &params, &method.body, state)?;
+        state.pop();
+        methods.push((method.name.clone(), *proc));
+    }
+    state.fields = Vec::new();
+    state.superclass = None;
+
+    Ok(Box::new(nameless::Expr::Class {
+        name: class.name.clone(),
+        superclass: superclass.map(|slot| Box::new(nameless::Expr::Global(slot))),
+        fields: field_names,
+        methods,
+    }))
+}
+
+#[allow(clippy::too_many_lines)]
 fn resolve_names_expr(
     expr: &ast::Expr,
     state: &mut StackState,
@@ -185,13 +491,60 @@ fn resolve_names_expr(
             }))
         }
 
-        ast::Expr::Call { proc, arg } => {
+        ast::Expr::Call { proc, args } => {
             let proc = resolve_names_expr(proc, state)?;
-            let arg = resolve_names_expr(arg, state)?;
+            let args = args
+                .iter()
+                .map(|arg| resolve_names_expr(arg, state).map(|arg| *arg))
+                .collect::<Result<Vec<_>, _>>()?;
+            for _ in &args {
+                state.pop();
+            }
             state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Call { proc, args }))
+        }
+
+        ast::Expr::LiteralString(s) => {
+            state.push();
+            Ok(Box::new(nameless::Expr::LiteralString(s.clone())))
+        }
+
+        ast::Expr::Concat { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Concat { left, right }))
+        }
+
+        ast::Expr::Length(e) => {
+            let e = resolve_names_expr(e, state)?;
             state.pop();
             state.push();
-            Ok(Box::new(nameless::Expr::Call { proc, arg }))
+            Ok(Box::new(nameless::Expr::Length(e)))
+        }
+
+        ast::Expr::Substring { string, start, end } => {
+            let string = resolve_names_expr(string, state)?;
+            let start = resolve_names_expr(start, state)?;
+            let end = resolve_names_expr(end, state)?;
+            state.pop();
+            state.pop();
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Substring { string, start, end }))
+        }
+
+        ast::Expr::IntToString(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::IntToString(e)))
+        }
+
+        ast::Expr::StringToInt(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::StringToInt(e)))
         }
 
         ast::Expr::LiteralInt(x) => {
@@ -206,13 +559,138 @@ fn resolve_names_expr(
             Ok(Box::new(nameless::Expr::Negate(e)))
         }
 
+        ast::Expr::Add { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Add { left, right }))
+        }
+
         ast::Expr::Subtract { left, right } => {
-            let left = resolve_names_expr(left, state)?;
-            let right = resolve_names_expr(right, state)?;
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Subtract { left, right }))
+        }
+
+        ast::Expr::Multiply { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Multiply { left, right }))
+        }
+
+        ast::Expr::Divide { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Divide { left, right }))
+        }
+
+        ast::Expr::Remainder { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Remainder { left, right }))
+        }
+
+        ast::Expr::And { left, right } => {
+            let (left, right) = resolve_names_short_circuit(left, right, state)?;
+            Ok(Box::new(nameless::Expr::And { left, right }))
+        }
+
+        ast::Expr::Or { left, right } => {
+            let (left, right) = resolve_names_short_circuit(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Or { left, right }))
+        }
+
+        ast::Expr::NewRef(e) => {
+            let e = resolve_names_expr(e, state)?;
             state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::NewRef(e)))
+        }
+
+        ast::Expr::DeRef(e) => {
+            let e = resolve_names_expr(e, state)?;
             state.pop();
             state.push();
-            Ok(Box::new(nameless::Expr::Subtract { left, right }))
+            Ok(Box::new(nameless::Expr::DeRef(e)))
+        }
+
+        ast::Expr::EmptyList => {
+            state.push();
+            Ok(Box::new(nameless::Expr::EmptyList))
+        }
+
+        ast::Expr::Cons { head, tail } => {
+            let (head, tail) = resolve_names_binary(head, tail, state)?;
+            Ok(Box::new(nameless::Expr::Cons { head, tail }))
+        }
+
+        ast::Expr::Car(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Car(e)))
+        }
+
+        ast::Expr::Cdr(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Cdr(e)))
+        }
+
+        ast::Expr::IsNull(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::IsNull(e)))
+        }
+
+        ast::Expr::List(exprs) => {
+            let mut nameless_exprs = Vec::new();
+            for expr in exprs {
+                nameless_exprs.push(*resolve_names_expr(expr, state)?);
+            }
+            for _ in exprs {
+                state.pop();
+            }
+            state.push();
+            Ok(Box::new(nameless::Expr::List(nameless_exprs)))
+        }
+
+        ast::Expr::SetRef { reference, value } => {
+            let (reference, value) = resolve_names_binary(reference, value, state)?;
+            Ok(Box::new(nameless::Expr::SetRef { reference, value }))
+        }
+
+        ast::Expr::Not(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Not(e)))
+        }
+
+        ast::Expr::Equal { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Equal { left, right }))
+        }
+
+        ast::Expr::NotEqual { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::NotEqual { left, right }))
+        }
+
+        ast::Expr::Less { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Less { left, right }))
+        }
+
+        ast::Expr::LessEqual { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::LessEqual { left, right }))
+        }
+
+        ast::Expr::Greater { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::Greater { left, right }))
+        }
+
+        ast::Expr::GreaterEqual { left, right } => {
+            let (left, right) = resolve_names_binary(left, right, state)?;
+            Ok(Box::new(nameless::Expr::GreaterEqual { left, right }))
         }
 
         ast::Expr::If {
@@ -241,59 +719,568 @@ fn resolve_names_expr(
         }
 
         ast::Expr::Let { name, expr, body } => {
+            let is_boxed = body.assigns(name);
             let expr = resolve_names_expr(expr, state)?;
-            state.begin_scope(name);
+            let expr = if is_boxed {
+                Box::new(nameless::Expr::NewRef(expr))
+            } else {
+                expr
+            };
+            state.begin_scope(&[(name, is_boxed)]);
             let body = resolve_names_expr(body, state)?;
-            state.end_scope();
+            state.end_scope(1);
             Ok(Box::new(nameless::Expr::Let { expr, body }))
         }
 
-        ast::Expr::LetRec {
-            name,
-            param,
-            proc_body,
-            let_body,
-            ..
-        } => {
-            let expr = resolve_names_proc(name, &param.name, proc_body, state)?;
-            state.begin_scope(name);
-            let body = resolve_names_expr(let_body, state)?;
-            state.end_scope();
-            Ok(Box::new(nameless::Expr::Let { expr, body }))
-        }
+        ast::Expr::LetRec { procs, body } => match procs.as_slice() {
+            // A procedure calls itself through the procedure slot of its
+            // frame.
+            [proc] => {
+                let expr = resolve_names_proc(&proc.name, &proc.params, &proc.body, state)?;
+                state.begin_scope(&[(&proc.name, false)]);
+                let body = resolve_names_expr(body, state)?;
+                state.end_scope(1);
+                Ok(Box::new(nameless::Expr::Let { expr, body }))
+            }
+            _ => resolve_names_let_rec_group(procs, body, state),
+        },
 
         ast::Expr::LiteralBool(value) => {
             state.push();
             Ok(Box::new(nameless::Expr::LiteralBool(*value)))
         }
 
-        ast::Expr::Proc { param, body } => resolve_names_proc("", &param.name, body, state),
+        ast::Expr::Proc { params, body } => resolve_names_proc("", params, body, state),
 
-        ast::Expr::Name(name) => {
+        ast::Expr::Name(name) => match resolve_name(name, state)? {
+            Location::Value(location, Storage::Value) => Ok(location),
+            Location::Value(location, _) => Ok(Box::new(nameless::Expr::DeRef(location))),
+            Location::Field(object, offset) => {
+                Ok(Box::new(nameless::Expr::ObjectField { object, offset }))
+            }
+        },
+
+        ast::Expr::Qualified { module, name } => {
+            let Some(&(_, binding)) = state
+                .modules
+                .lookup(module)
+                .and_then(|values| values.iter().rev().find(|(value, _)| value == name))
+            else {
+                return Err(format!("undefined name: from {module} take {name}"));
+            };
             state.push();
-            if let Some(&stack_offset) = state.lookup_local(name) {
-                Ok(Box::new(nameless::Expr::Local(stack_offset)))
-            } else if let Some(capture_offset) = state.lookup_capture(name) {
-                Ok(Box::new(nameless::Expr::Capture(capture_offset)))
-            } else if let Some(&stack_offset) = state.globals.lookup(name) {
-                Ok(Box::new(nameless::Expr::Global(stack_offset)))
+            let location = Box::new(nameless::Expr::Global(binding.stack_offset));
+            if binding.storage.is_boxed() {
+                Ok(Box::new(nameless::Expr::DeRef(location)))
             } else {
-                Err(format!("undefined name: {name}"))
+                Ok(location)
+            }
+        }
+
+        ast::Expr::Assign { name, expr } => {
+            let location = resolve_name(name, state)?;
+            if let Location::Value(_, Storage::Value | Storage::SharedBox) = location {
+                return Err(format!("`{name}` cannot be assigned"));
+            }
+            let value = resolve_names_expr(expr, state)?;
+            state.pop();
+            state.pop();
+            state.push();
+            match location {
+                Location::Value(reference, _) => {
+                    Ok(Box::new(nameless::Expr::SetRef { reference, value }))
+                }
+                Location::Field(object, offset) => Ok(Box::new(nameless::Expr::SetObjectField {
+                    object,
+                    offset,
+                    value,
+                })),
+            }
+        }
+
+        ast::Expr::SelfObject => match resolve_name("self", state)? {
+            Location::Value(location, _) => Ok(location),
+            Location::Field(..) => unreachable!("`self` is not a field"),
+        },
+
+        ast::Expr::NewObject { class, args } => {
+            let Some(layout) = state.classes.lookup(class) else {
+                return Err(format!("undefined class `{class}`"));
+            };
+            let class = Box::new(nameless::Expr::Global(layout.slot));
+            state.push();
+            let args = resolve_names_args(args, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::NewObject { class, args }))
+        }
+
+        ast::Expr::Send {
+            object,
+            method,
+            args,
+        } => {
+            let object = resolve_names_expr(object, state)?;
+            let args = resolve_names_args(args, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Send {
+                object,
+                method: method.clone(),
+                args,
+            }))
+        }
+
+        ast::Expr::Super { method, args } => {
+            let Some(superclass) = state.superclass else {
+                return Err(format!("`object` has no method `{method}`"));
+            };
+            let object = resolve_names_expr(&ast::Expr::SelfObject, state)?;
+            let args = resolve_names_args(args, state)?;
+            let class = Box::new(nameless::Expr::Global(superclass));
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::SuperSend {
+                object,
+                args,
+                class,
+                method: method.clone(),
+            }))
+        }
+
+        ast::Expr::Begin(exprs) => {
+            let mut nameless_exprs = Vec::new();
+            for (i, expr) in exprs.iter().enumerate() {
+                if i > 0 {
+                    // The previous expression's value is discarded.
+                    state.pop();
+                }
+                nameless_exprs.push(*resolve_names_expr(expr, state)?);
             }
+            Ok(Box::new(nameless::Expr::Begin(nameless_exprs)))
         }
+
+        ast::Expr::Data { variants, body, .. } => {
+            let mut constructors = Vec::new();
+            for (tag, variant) in variants.iter().enumerate() {
+                constructors.push(constructor(&variant.name, tag, variant.t_fields.len()));
+                state.push();
+                state.begin_scope(&[(&variant.name, false)]);
+                state.constructors.push(variant.name.clone(), tag);
+            }
+            let mut body = resolve_names_expr(body, state)?;
+            for _ in variants {
+                state.constructors.pop();
+            }
+            state.end_scope(variants.len());
+            for expr in constructors.into_iter().rev() {
+                let expr = Box::new(expr);
+                body = Box::new(nameless::Expr::Let { expr, body });
+            }
+            Ok(body)
+        }
+
+        ast::Expr::Match { expr, arms } => {
+            let expr = resolve_names_expr(expr, state)?;
+            // The matched value is replaced by its fields.
+            state.pop();
+            let mut nameless_arms = Vec::new();
+            for arm in arms {
+                let Some(&tag) = state.constructors.lookup(&arm.constructor) else {
+                    return Err(format!("undefined constructor `{}`", arm.constructor));
+                };
+                state.save_stack();
+                for _ in &arm.names {
+                    state.push();
+                }
+                let names: Vec<(&str, bool)> = arm
+                    .names
+                    .iter()
+                    .map(|name| (name.as_str(), arm.body.assigns(name)))
+                    .collect();
+                state.begin_scope(&names);
+                let boxed = names
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, is_boxed))| *is_boxed)
+                    .map(|(i, _)| state.stack_top - StackOffset(names.len() - i))
+                    .collect();
+                let body = *resolve_names_expr(&arm.body, state)?;
+                state.end_scope(names.len());
+                state.restore_stack();
+                nameless_arms.push(nameless::Arm {
+                    tag,
+                    count: names.len(),
+                    boxed,
+                    body,
+                });
+            }
+            state.push();
+            Ok(Box::new(nameless::Expr::Match {
+                expr,
+                arms: nameless_arms,
+            }))
+        }
+
+        ast::Expr::Record(fields) => {
+            let mut nameless_fields = Vec::new();
+            for (name, expr) in fields {
+                nameless_fields.push((name.clone(), *resolve_names_expr(expr, state)?));
+            }
+            for _ in fields {
+                state.pop();
+            }
+            state.push();
+            Ok(Box::new(nameless::Expr::Record(nameless_fields)))
+        }
+
+        ast::Expr::Field { record, offset, .. } => {
+            let record = resolve_names_expr(record, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Field {
+                record,
+                offset: offset.get(),
+            }))
+        }
+
+        ast::Expr::Update {
+            record,
+            fields,
+            offsets,
+        } => {
+            let record = resolve_names_expr(record, state)?;
+            let mut values = Vec::new();
+            for (_, expr) in fields {
+                values.push(*resolve_names_expr(expr, state)?);
+            }
+            for _ in fields {
+                state.pop();
+            }
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Update {
+                record,
+                offsets: offsets.borrow().clone(),
+                values,
+            }))
+        }
+
+        ast::Expr::LetCc { name, body } => {
+            // The continuation is pushed where the `letcc`'s value will be.
+            state.push();
+            let is_boxed = body.assigns(name);
+            state.begin_scope(&[(name, is_boxed)]);
+            let boxed = is_boxed.then(|| state.stack_top - StackOffset(1));
+            let body = resolve_names_expr(body, state)?;
+            state.end_scope(1);
+            Ok(Box::new(nameless::Expr::LetCc { boxed, body }))
+        }
+
+        ast::Expr::CallCc(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::CallCc(e)))
+        }
+
+        ast::Expr::Throw { value, cont } => {
+            let (value, cont) = resolve_names_binary(value, cont, state)?;
+            Ok(Box::new(nameless::Expr::Throw { value, cont }))
+        }
+
+        ast::Expr::Spawn(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Spawn(e)))
+        }
+
+        ast::Expr::Yield => {
+            state.push();
+            Ok(Box::new(nameless::Expr::Yield))
+        }
+
+        ast::Expr::NewMutex => {
+            state.push();
+            Ok(Box::new(nameless::Expr::NewMutex))
+        }
+
+        ast::Expr::Wait(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Wait(e)))
+        }
+
+        ast::Expr::Signal(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Signal(e)))
+        }
+
+        ast::Expr::Raise(e) => {
+            let e = resolve_names_expr(e, state)?;
+            state.pop();
+            state.push();
+            Ok(Box::new(nameless::Expr::Raise(e)))
+        }
+
+        ast::Expr::Try {
+            body,
+            name,
+            handler,
+        } => {
+            state.save_stack();
+            let body = resolve_names_expr(body, state)?;
+            state.restore_stack();
+            // The exception's value is pushed where the body's value would
+            // have been.
+            state.push();
+            let is_boxed = handler.assigns(name);
+            state.begin_scope(&[(name, is_boxed)]);
+            let boxed = is_boxed.then(|| state.stack_top - StackOffset(1));
+            let handler = resolve_names_expr(handler, state)?;
+            state.end_scope(1);
+            Ok(Box::new(nameless::Expr::Try {
+                body,
+                boxed,
+                handler,
+            }))
+        }
+
+        ast::Expr::Tuple(exprs) => {
+            let mut nameless_exprs = Vec::new();
+            for expr in exprs {
+                nameless_exprs.push(*resolve_names_expr(expr, state)?);
+            }
+            for _ in exprs {
+                state.pop();
+            }
+            state.push();
+            Ok(Box::new(nameless::Expr::Tuple(nameless_exprs)))
+        }
+
+        ast::Expr::Unpack { names, expr, body } => {
+            let expr = resolve_names_expr(expr, state)?;
+            state.pop();
+            for _ in names {
+                state.push();
+            }
+            let scope_names: Vec<(&str, bool)> = names
+                .iter()
+                .map(|name| (name.as_str(), body.assigns(name)))
+                .collect();
+            state.begin_scope(&scope_names);
+            let boxed = scope_names
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, is_boxed))| *is_boxed)
+                .map(|(i, _)| state.stack_top - StackOffset(names.len() - i))
+                .collect();
+            let body = resolve_names_expr(body, state)?;
+            state.end_scope(names.len());
+            Ok(Box::new(nameless::Expr::Unpack {
+                expr,
+                count: names.len(),
+                boxed,
+                body,
+            }))
+        }
+    }
+}
+
+/// Resolves a name to the expression which pushes the name's value, or the
+/// value's box if the name is boxed.
+fn resolve_name(name: &str, state: &mut StackState) -> Result<Location, String> {
+    state.push();
+    if let Some((location, storage)) = lookup_proc_name(name, state) {
+        Ok(Location::Value(location, storage))
+    } else if let Some(offset) = state.fields.iter().rposition(|field| field == name) {
+        let (object, _) = lookup_proc_name("self", state).expect("a method binds `self`");
+        Ok(Location::Field(object, offset))
+    } else if let Some(&binding) = state.globals.lookup(name) {
+        let location = nameless::Expr::Global(binding.stack_offset);
+        Ok(Location::Value(Box::new(location), binding.storage))
+    } else {
+        Err(format!("undefined name: {name}"))
+    }
+}
+
+/// Finds a name bound in the current procedure or captured from an enclosing
+/// one.
+fn lookup_proc_name(name: &str, state: &mut StackState) -> Option<(Box<nameless::Expr>, Storage)> {
+    if let Some(&binding) = state.lookup_local(name) {
+        let location = nameless::Expr::Local(binding.stack_offset);
+        Some((Box::new(location), binding.storage))
+    } else {
+        state.lookup_capture(name).map(|(capture_offset, storage)| {
+            (Box::new(nameless::Expr::Capture(capture_offset)), storage)
+        })
     }
 }
 
+/// Resolves the names in the arguments of a method call. The arguments'
+/// values are left on the stack.
+fn resolve_names_args(
+    args: &[ast::Expr],
+    state: &mut StackState,
+) -> Result<Vec<nameless::Expr>, String> {
+    let args = args
+        .iter()
+        .map(|arg| resolve_names_expr(arg, state).map(|arg| *arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    for _ in &args {
+        state.pop();
+    }
+    Ok(args)
+}
+
+type NamelessOperands = (Box<nameless::Expr>, Box<nameless::Expr>);
+
+fn resolve_names_binary(
+    left: &ast::Expr,
+    right: &ast::Expr,
+    state: &mut StackState,
+) -> Result<NamelessOperands, String> {
+    let left = resolve_names_expr(left, state)?;
+    let right = resolve_names_expr(right, state)?;
+    state.pop();
+    state.pop();
+    state.push();
+    Ok((left, right))
+}
+
+fn resolve_names_short_circuit(
+    left: &ast::Expr,
+    right: &ast::Expr,
+    state: &mut StackState,
+) -> Result<NamelessOperands, String> {
+    // The left value is popped by a conditional jump, so only one of the
+    // right value or a literal is left on the stack.
+    let left = resolve_names_expr(left, state)?;
+    state.pop();
+    let right = resolve_names_expr(right, state)?;
+    Ok((left, right))
+}
+
+/// Gives the expression for a constructor's value. A constructor with fields
+/// is a procedure taking the fields as parameters.
+fn constructor(name: &str, tag: usize, field_count: usize) -> nameless::Expr {
+    if field_count == 0 {
+        nameless::Expr::Variant {
+            name: name.to_string(),
+            tag,
+            fields: Vec::new(),
+        }
+    } else {
+        let fields = (1..=field_count)
+            .map(|i| nameless::Expr::Local(StackOffset(i)))
+            .collect();
+        let body = Box::new(nameless::Expr::Variant {
+            name: name.to_string(),
+            tag,
+            fields,
+        });
+        nameless::Expr::Proc {
+            body,
+            captures: Vec::new(),
+            boxed_params: Vec::new(),
+        }
+    }
+}
+
+fn resolve_names_let_rec_group(
+    procs: &[ast::RecProc],
+    body: &ast::Expr,
+    state: &mut StackState,
+) -> Result<Box<nameless::Expr>, String> {
+    let (boxes, mut exprs) = resolve_names_rec_group(procs, state)?;
+    exprs.push(*resolve_names_expr(body, state)?);
+    state.end_scope(procs.len());
+
+    let mut expr = Box::new(nameless::Expr::Begin(exprs));
+    for box_expr in boxes.into_iter().rev() {
+        expr = Box::new(nameless::Expr::Let {
+            expr: Box::new(box_expr),
+            body: expr,
+        });
+    }
+    Ok(expr)
+}
+
+/// Resolves the names in a group of procedures defined together by `letrec`
+/// or `defrec`. A procedure's captures are copied when it is made, so each
+/// procedure is kept in a box made before any of them, which the others
+/// capture. Gives the expressions making the boxes, whose names are then in
+/// scope, and the expressions setting each box to its procedure.
+fn resolve_names_rec_group(
+    procs: &[ast::RecProc],
+    state: &mut StackState,
+) -> Result<(Vec<nameless::Expr>, Vec<nameless::Expr>), String> {
+    let mut boxes = Vec::new();
+    for proc in procs {
+        // The box's first value is never used.
+        let placeholder = Box::new(nameless::Expr::Unit);
+        boxes.push(nameless::Expr::NewRef(placeholder));
+        state.push();
+        state.begin_shared_scope(&proc.name);
+    }
+    let mut exprs = Vec::new();
+    for proc in procs {
+        let Location::Value(reference, _) = resolve_name(&proc.name, state)? else {
+            unreachable!("a recursive procedure is not a field");
+        };
+        let value = resolve_names_proc(&proc.name, &proc.params, &proc.body, state)?;
+        // Each value is discarded by `Begin` but the last, which is counted
+        // by the caller.
+        state.pop();
+        state.pop();
+        exprs.push(nameless::Expr::SetRef { reference, value });
+    }
+    Ok((boxes, exprs))
+}
+
 fn resolve_names_proc(
     proc_name: &str,
-    param_name: &str,
+    params: &[ast::Param],
+    body: &ast::Expr,
+    state: &mut StackState,
+) -> Result<Box<nameless::Expr>, String> {
+    let params: Vec<(&str, bool)> = params
+        .iter()
+        .map(|param| (param.name.as_str(), body.assigns(&param.name)))
+        .collect();
+    resolve_names_proc_params(proc_name, &params, body, state)
+}
+
+/// Resolves the names in a procedure given its parameters' names and whether
+/// they are boxed.
+fn resolve_names_proc_params(
+    proc_name: &str,
+    params: &[(&str, bool)],
     body: &ast::Expr,
     state: &mut StackState,
 ) -> Result<Box<nameless::Expr>, String> {
-    state.begin_proc(proc_name, param_name);
+    state.begin_proc(proc_name, params);
     let body = resolve_names_expr(body, state)?;
     let CaptureTable(capture_table) = state.end_proc();
-    let captures: Vec<Capture> = capture_table.items.iter().map(|item| item.value).collect();
+    let captures: Vec<Capture> = capture_table
+        .items
+        .iter()
+        .map(|item| item.value.capture)
+        .collect();
+    let boxed_params = params
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, is_boxed))| *is_boxed)
+        .map(|(i, _)| StackOffset(i + 1))
+        .collect();
     state.push();
-    Ok(Box::new(nameless::Expr::Proc { body, captures }))
+    Ok(Box::new(nameless::Expr::Proc {
+        body,
+        captures,
+        boxed_params,
+    }))
 }
@@ -1,41 +1,133 @@
 //! A recursive decent letpl parser.
 
-use crate::ast::{Expr, Param, Program};
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+
+use crate::ast::{
+    Arm, Class, Declaration, Def, Definition, Expr, Import, Library, Method, Module, Param,
+    Program, RecProc, Variant,
+};
 use crate::scanner::{Scanner, Token, TokenTag};
 use crate::types::Type;
 
 type ExprResult = Result<Box<Expr>, String>;
 
+/// An error found while parsing a program.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The source text ended before the program did, so more text could
+    /// complete it.
+    Incomplete(String),
+
+    /// The source text is not a program, however it continues.
+    Invalid(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Incomplete(msg) | ParseError::Invalid(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
 /// Parses a given source text, giving an AST representing the program.
-pub fn parse(src: &str) -> Result<Program, String> {
-    let mut parser = Parser::new(src)?;
-    parser.program()
+pub fn parse(src: &str) -> Result<Program, ParseError> {
+    let mut parser = Parser::new(src);
+    let result = parser.advance().and_then(|()| parser.program());
+    result.map_err(|msg| {
+        if parser.is_incomplete {
+            ParseError::Incomplete(msg)
+        } else {
+            ParseError::Invalid(msg)
+        }
+    })
+}
+
+/// Tests if a given source text is a whole program whose last class
+/// declaration, `defrec` group or `match` could be continued by more text.
+pub fn can_continue(src: &str) -> bool {
+    let mut parser = Parser::new(src);
+    let result = parser.advance().and_then(|()| parser.program());
+    result.is_ok() && parser.is_continuable
+}
+
+/// Parses a given source text, giving an AST representing the library.
+pub fn parse_library(src: &str) -> Result<Library, String> {
+    let mut parser = Parser::new(src);
+    parser.advance()?;
+    let library = parser.declarations()?;
+    parser.expect(TokenTag::Eof)?;
+    Ok(library)
+}
+
+/// Parses a given source text as the prelude, a sequence of top-level
+/// definitions.
+pub fn parse_prelude(src: &str) -> Result<Vec<Def>, String> {
+    let mut parser = Parser::new(src);
+    parser.advance()?;
+    let defs = parser.defs()?;
+    parser.expect(TokenTag::Eof)?;
+    Ok(defs)
 }
 
 struct Parser<'a> {
     scanner: Scanner<'a>,
     current: Token,
+
+    /// Whether parsing failed because the source text ended too soon.
+    is_incomplete: bool,
+
+    /// Whether the source text ended where a class declaration, `defrec`
+    /// group or `match` could have gone on.
+    is_continuable: bool,
 }
 
-impl<'a> Parser<'a> {
-    fn new(src: &str) -> Result<Parser, String> {
-        let mut scanner = Scanner::new(src);
-        let current = scanner.next_token()?;
-        Ok(Parser { scanner, current })
+impl Parser<'_> {
+    /// Creates a parser for a given source text, which must be advanced to
+    /// the text's first token before parsing.
+    fn new(src: &str) -> Parser<'_> {
+        Parser {
+            scanner: Scanner::new(src),
+            current: Token::new(TokenTag::Eof, 1),
+            is_incomplete: false,
+            is_continuable: false,
+        }
     }
 
     fn advance(&mut self) -> Result<(), String> {
-        self.current = self.scanner.next_token()?;
-        Ok(())
+        match self.scanner.next_token() {
+            Ok(token) => {
+                self.current = token;
+                Ok(())
+            }
+            Err(msg) => {
+                self.is_incomplete = self.scanner.is_at_end();
+                Err(msg)
+            }
+        }
     }
 
+    /// Gives an error about the current token, noting if the source text
+    /// ended where more was expected.
+    fn unexpected(&mut self, msg: String) -> String {
+        if self.current.tag == TokenTag::Eof {
+            self.is_incomplete = true;
+        }
+        msg
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
     fn expect(&mut self, expected: TokenTag) -> Result<(), String> {
         if self.current.tag == expected {
             self.advance()?;
             Ok(())
         } else {
             let message = format!("expected `{:}` but got `{:}`", expected, self.current.tag);
-            Err(message)
+            Err(self.unexpected(message))
         }
     }
 
@@ -46,10 +138,11 @@ impl<'a> Parser<'a> {
             Ok(name)
         } else {
             let msg = format!("expected identifier but found {:}", self.current.tag);
-            Err(msg)
+            Err(self.unexpected(msg))
         }
     }
 
+    #[allow(clippy::needless_pass_by_value)]
     fn is_match(&mut self, token_tag: TokenTag) -> Result<bool, String> {
         let is_match = self.current.tag == token_tag;
         if is_match {
@@ -58,19 +151,212 @@ impl<'a> Parser<'a> {
         Ok(is_match)
     }
 
+    /// Matches a token which would go on with a class declaration, `defrec`
+    /// group or `match`, noting if the source text ended where one could
+    /// have.
+    #[allow(clippy::needless_pass_by_value)]
+    fn is_continued_by(&mut self, token_tag: TokenTag) -> Result<bool, String> {
+        if self.current.tag == TokenTag::Eof {
+            self.is_continuable = true;
+        }
+        self.is_match(token_tag)
+    }
+
     fn program(&mut self) -> Result<Program, String> {
-        let expr = self.expr()?;
+        let Library {
+            imports,
+            modules,
+            classes,
+            defs,
+        } = self.declarations()?;
+        let expr = if self.current.tag == TokenTag::Eof {
+            None
+        } else {
+            Some(self.expr()?)
+        };
         self.expect(TokenTag::Eof)?;
-        Ok(Program { expr })
+        Ok(Program {
+            imports,
+            modules,
+            classes,
+            defs,
+            expr,
+        })
+    }
+
+    /// Parses the imports, modules, classes and definitions which start a
+    /// program or make up a library.
+    fn declarations(&mut self) -> Result<Library, String> {
+        let mut imports = Vec::new();
+        while self.is_match(TokenTag::Import)? {
+            imports.push(self.import()?);
+        }
+        let mut modules = Vec::new();
+        while self.current.tag == TokenTag::Module {
+            modules.push(self.module_decl()?);
+        }
+        let mut classes = Vec::new();
+        while self.current.tag == TokenTag::Class {
+            classes.push(self.class_decl()?);
+        }
+        let defs = self.defs()?;
+        Ok(Library {
+            imports,
+            modules,
+            classes,
+            defs,
+        })
+    }
+
+    fn defs(&mut self) -> Result<Vec<Def>, String> {
+        let mut defs = Vec::new();
+        loop {
+            if self.is_match(TokenTag::Def)? {
+                let name = self.expect_identifer()?;
+                self.expect(TokenTag::Equal)?;
+                let expr = *self.expr()?;
+                defs.push(Def::Value { name, expr });
+            } else if self.is_match(TokenTag::DefRec)? {
+                defs.push(Def::Rec(self.rec_procs()?));
+            } else {
+                return Ok(defs);
+            }
+        }
+    }
+
+    fn import(&mut self) -> Result<Import, String> {
+        match &self.current.tag {
+            TokenTag::StringLiteral(path) => {
+                let path = path.clone();
+                self.advance()?;
+                Ok(Import::Path(path))
+            }
+            _ => Ok(Import::Name(self.expect_identifer()?)),
+        }
+    }
+
+    fn module_decl(&mut self) -> Result<Module, String> {
+        self.advance()?;
+        let name = self.expect_identifer()?;
+        self.expect(TokenTag::Interface)?;
+        self.expect(TokenTag::LeftBracket)?;
+        let mut interface = Vec::new();
+        while !self.is_match(TokenTag::RightBracket)? {
+            interface.push(self.declaration()?);
+        }
+        self.expect(TokenTag::Body)?;
+        self.expect(TokenTag::LeftBracket)?;
+        let mut body = Vec::new();
+        while !self.is_match(TokenTag::RightBracket)? {
+            body.push(self.definition()?);
+        }
+
+        Ok(Module {
+            name,
+            interface,
+            body,
+        })
+    }
+
+    fn declaration(&mut self) -> Result<Declaration, String> {
+        if self.is_match(TokenTag::Opaque)? {
+            Ok(Declaration::Opaque(self.expect_identifer()?))
+        } else if self.is_match(TokenTag::Transparent)? {
+            let name = self.expect_identifer()?;
+            self.expect(TokenTag::Equal)?;
+            let t = self.parse_type()?;
+            Ok(Declaration::Transparent { name, t })
+        } else {
+            let (name, t) = self.field_type()?;
+            Ok(Declaration::Value { name, t })
+        }
+    }
+
+    fn definition(&mut self) -> Result<Definition, String> {
+        if self.is_match(TokenTag::Type)? {
+            let name = self.expect_identifer()?;
+            self.expect(TokenTag::Equal)?;
+            let t = self.parse_type()?;
+            Ok(Definition::Type { name, t })
+        } else {
+            let name = self.expect_identifer()?;
+            self.expect(TokenTag::Equal)?;
+            let expr = *self.expr()?;
+            Ok(Definition::Value { name, expr })
+        }
+    }
+
+    /// Parses the module and name of a qualified reference, `from m take x`.
+    fn qualified(&mut self) -> Result<(String, String), String> {
+        self.advance()?;
+        let module = self.expect_identifer()?;
+        self.expect(TokenTag::Take)?;
+        let name = self.expect_identifer()?;
+        Ok((module, name))
+    }
+
+    fn class_decl(&mut self) -> Result<Class, String> {
+        self.advance()?;
+        let name = self.expect_identifer()?;
+        self.expect(TokenTag::Extends)?;
+        let superclass = self.expect_identifer()?;
+        let mut fields = Vec::new();
+        while self.is_continued_by(TokenTag::Field)? {
+            let t = self.parse_type()?;
+            fields.push((self.expect_identifer()?, t));
+        }
+        let mut methods = Vec::new();
+        while self.is_continued_by(TokenTag::Method)? {
+            let t_result = self.parse_type()?;
+            let name = self.expect_identifer()?;
+            let params = self.params()?;
+            let body = *self.expr()?;
+            methods.push(Method {
+                name,
+                t_result,
+                params,
+                body,
+            });
+        }
+
+        Ok(Class {
+            name,
+            superclass,
+            fields,
+            methods,
+        })
     }
 
     fn expr(&mut self) -> ExprResult {
+        let expr = self.primary_expr()?;
+        self.field_accesses(expr)
+    }
+
+    /// Parses any field accesses following an expression.
+    fn field_accesses(&mut self, mut expr: Box<Expr>) -> ExprResult {
+        while self.is_match(TokenTag::Dot)? {
+            let name = self.expect_identifer()?;
+            expr = Box::new(Expr::Field {
+                record: expr,
+                name,
+                offset: Cell::new(0),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn primary_expr(&mut self) -> ExprResult {
         match &self.current.tag {
             TokenTag::Number(x) => {
                 let x = *x;
                 self.advance()?;
                 Ok(Box::new(Expr::LiteralInt(x)))
             }
+            TokenTag::StringLiteral(s) => {
+                let s = s.clone();
+                self.advance()?;
+                Ok(Box::new(Expr::LiteralString(s)))
+            }
             TokenTag::True => {
                 self.advance()?;
                 Ok(Box::new(Expr::LiteralBool(true)))
@@ -80,8 +366,41 @@ impl<'a> Parser<'a> {
                 Ok(Box::new(Expr::LiteralBool(false)))
             }
             TokenTag::MinusSign => self.diff(),
+            TokenTag::Plus => self.binary(|left, right| Expr::Add { left, right }),
+            TokenTag::Star => self.binary(|left, right| Expr::Multiply { left, right }),
+            TokenTag::Slash => self.binary(|left, right| Expr::Divide { left, right }),
+            TokenTag::Percent => self.binary(|left, right| Expr::Remainder { left, right }),
+            TokenTag::Equal => self.binary(|left, right| Expr::Equal { left, right }),
+            TokenTag::NotEqual => self.binary(|left, right| Expr::NotEqual { left, right }),
+            TokenTag::Less => self.binary(|left, right| Expr::Less { left, right }),
+            TokenTag::LessEqual => self.binary(|left, right| Expr::LessEqual { left, right }),
+            TokenTag::Greater => self.binary(|left, right| Expr::Greater { left, right }),
+            TokenTag::GreaterEqual => self.binary(|left, right| Expr::GreaterEqual { left, right }),
             TokenTag::IsZero => self.is_zero(),
+            TokenTag::And => self.binary(|left, right| Expr::And { left, right }),
+            TokenTag::Or => self.binary(|left, right| Expr::Or { left, right }),
+            TokenTag::Not => self.unary(Expr::Not),
+            TokenTag::NewRef => self.unary(Expr::NewRef),
+            TokenTag::DeRef => self.unary(Expr::DeRef),
+            TokenTag::SetRef => self.binary(|reference, value| Expr::SetRef { reference, value }),
+            TokenTag::EmptyList => {
+                self.advance()?;
+                Ok(Box::new(Expr::EmptyList))
+            }
+            TokenTag::Cons => self.binary(|head, tail| Expr::Cons { head, tail }),
+            TokenTag::Car => self.unary(Expr::Car),
+            TokenTag::Cdr => self.unary(Expr::Cdr),
+            TokenTag::IsNull => self.unary(Expr::IsNull),
+            TokenTag::LeftBracket => self.list_expr(),
+            TokenTag::Concat => self.binary(|left, right| Expr::Concat { left, right }),
+            TokenTag::Length => self.unary(Expr::Length),
+            TokenTag::Substring => self.substring(),
+            TokenTag::IntToString => self.unary(Expr::IntToString),
+            TokenTag::StringToInt => self.unary(Expr::StringToInt),
+            TokenTag::LeftBrace => self.record_expr(),
             TokenTag::Assert => self.assert(),
+            TokenTag::Set => self.assign(),
+            TokenTag::Begin => self.begin(),
             TokenTag::If => self.if_expr(),
             TokenTag::Identifier(name) => {
                 let name = name.clone();
@@ -89,10 +408,41 @@ impl<'a> Parser<'a> {
                 Ok(Box::new(Expr::Name(name)))
             }
             TokenTag::Let => self.let_expr(),
+            TokenTag::From => {
+                let (module, name) = self.qualified()?;
+                Ok(Box::new(Expr::Qualified { module, name }))
+            }
+            TokenTag::Unpack => self.unpack_expr(),
+            TokenTag::Raise => {
+                self.advance()?;
+                let expr = self.expr()?;
+                Ok(Box::new(Expr::Raise(expr)))
+            }
+            TokenTag::Try => self.try_expr(),
+            TokenTag::LetCc => self.let_cc_expr(),
+            TokenTag::CallCc => self.unary(Expr::CallCc),
+            TokenTag::Throw => self.throw_expr(),
+            TokenTag::Spawn => self.unary(Expr::Spawn),
+            TokenTag::Yield => self.nullary(Expr::Yield),
+            TokenTag::Mutex => self.nullary(Expr::NewMutex),
+            TokenTag::Wait => self.unary(Expr::Wait),
+            TokenTag::Signal => self.unary(Expr::Signal),
+            TokenTag::New => self.new_object_expr(),
+            TokenTag::Send => self.send_expr(),
+            TokenTag::Super => self.super_expr(),
+            TokenTag::SelfObject => {
+                self.advance()?;
+                Ok(Box::new(Expr::SelfObject))
+            }
+            TokenTag::Data => self.data_expr(),
+            TokenTag::Match => self.match_expr(),
             TokenTag::LetRec => self.let_rec_expr(),
             TokenTag::Proc => self.proc_expr(),
             TokenTag::LeftParen => self.call_expr(),
-            unexpected_token => Err(format!("unexpected token `{unexpected_token:}`")),
+            unexpected_token => {
+                let msg = format!("unexpected token `{unexpected_token:}`");
+                Err(self.unexpected(msg))
+            }
         }
     }
 
@@ -110,6 +460,17 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn binary(&mut self, make_expr: fn(Box<Expr>, Box<Expr>) -> Expr) -> ExprResult {
+        self.advance()?;
+        self.expect(TokenTag::LeftParen)?;
+        let left = self.expr()?;
+        self.expect(TokenTag::Comma)?;
+        let right = self.expr()?;
+        self.expect(TokenTag::RightParen)?;
+
+        Ok(Box::new(make_expr(left, right)))
+    }
+
     fn is_zero(&mut self) -> ExprResult {
         self.advance()?;
         self.expect(TokenTag::LeftParen)?;
@@ -119,6 +480,23 @@ impl<'a> Parser<'a> {
         Ok(Box::new(Expr::IsZero(expr)))
     }
 
+    fn nullary(&mut self, expr: Expr) -> ExprResult {
+        self.advance()?;
+        self.expect(TokenTag::LeftParen)?;
+        self.expect(TokenTag::RightParen)?;
+
+        Ok(Box::new(expr))
+    }
+
+    fn unary(&mut self, make_expr: fn(Box<Expr>) -> Expr) -> ExprResult {
+        self.advance()?;
+        self.expect(TokenTag::LeftParen)?;
+        let expr = self.expr()?;
+        self.expect(TokenTag::RightParen)?;
+
+        Ok(Box::new(make_expr(expr)))
+    }
+
     fn assert(&mut self) -> ExprResult {
         let line = self.current.line;
         self.advance()?;
@@ -129,6 +507,26 @@ impl<'a> Parser<'a> {
         Ok(Box::new(Expr::Assert { line, test, body }))
     }
 
+    fn assign(&mut self) -> ExprResult {
+        self.advance()?;
+        let name = self.expect_identifer()?;
+        self.expect(TokenTag::Equal)?;
+        let expr = self.expr()?;
+
+        Ok(Box::new(Expr::Assign { name, expr }))
+    }
+
+    fn begin(&mut self) -> ExprResult {
+        self.advance()?;
+        let mut exprs = vec![*self.expr()?];
+        while self.is_match(TokenTag::Semicolon)? {
+            exprs.push(*self.expr()?);
+        }
+        self.expect(TokenTag::End)?;
+
+        Ok(Box::new(Expr::Begin(exprs)))
+    }
+
     fn if_expr(&mut self) -> ExprResult {
         self.advance()?;
         let test = self.expr()?;
@@ -157,52 +555,341 @@ impl<'a> Parser<'a> {
 
     fn let_rec_expr(&mut self) -> ExprResult {
         self.advance()?;
-        let t_result = self.parse_type()?;
+        let procs = self.rec_procs()?;
+        self.expect(TokenTag::In)?;
+        let body = self.expr()?;
+
+        Ok(Box::new(Expr::LetRec { procs, body }))
+    }
+
+    /// Parses procedures joined by `and`.
+    fn rec_procs(&mut self) -> Result<Vec<RecProc>, String> {
+        let mut procs = vec![self.rec_proc()?];
+        while self.is_continued_by(TokenTag::And)? {
+            procs.push(self.rec_proc()?);
+        }
+        Ok(procs)
+    }
+
+    fn rec_proc(&mut self) -> Result<RecProc, String> {
+        // A type name and a procedure name are both identifiers, so an
+        // identifier is only the type if another identifier follows it.
+        let (t_result, name) = if let TokenTag::Identifier(_) = self.current.tag {
+            let first = self.expect_identifer()?;
+            if let TokenTag::Identifier(_) = self.current.tag {
+                (Some(Type::new_named(first)), self.expect_identifer()?)
+            } else {
+                (None, first)
+            }
+        } else {
+            (Some(self.parse_type()?), self.expect_identifer()?)
+        };
+        let params = self.params()?;
+        let body = *self.expr()?;
+
+        Ok(RecProc {
+            t_result,
+            name,
+            params,
+            body,
+        })
+    }
+
+    fn proc_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let params = self.params()?;
+        let body = self.expr()?;
+
+        Ok(Box::new(Expr::Proc { params, body }))
+    }
+
+    fn call_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let proc = self.expr()?;
+        if self.current.tag == TokenTag::Comma {
+            return self.tuple_expr(*proc);
+        }
+        let mut args = Vec::new();
+        while !self.is_match(TokenTag::RightParen)? {
+            let arg = self.expr()?;
+            args.push(*arg);
+        }
+
+        Ok(Box::new(Expr::Call { proc, args }))
+    }
+
+    fn new_object_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let class = self.expect_identifer()?;
+        let args = self.args()?;
+
+        Ok(Box::new(Expr::NewObject { class, args }))
+    }
+
+    fn send_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let object = self.expr()?;
+        let method = self.expect_identifer()?;
+        let args = self.args()?;
+
+        Ok(Box::new(Expr::Send {
+            object,
+            method,
+            args,
+        }))
+    }
+
+    fn super_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let method = self.expect_identifer()?;
+        let args = self.args()?;
+
+        Ok(Box::new(Expr::Super { method, args }))
+    }
+
+    /// Parses a method's arguments, which are separated by commas.
+    fn args(&mut self) -> Result<Vec<Expr>, String> {
+        self.expect(TokenTag::LeftParen)?;
+        let mut args = Vec::new();
+        if !self.is_match(TokenTag::RightParen)? {
+            args.push(*self.expr()?);
+            while self.is_match(TokenTag::Comma)? {
+                args.push(*self.expr()?);
+            }
+            self.expect(TokenTag::RightParen)?;
+        }
+        Ok(args)
+    }
+
+    fn let_cc_expr(&mut self) -> ExprResult {
+        self.advance()?;
         let name = self.expect_identifer()?;
+        self.expect(TokenTag::In)?;
+        let body = self.expr()?;
+
+        Ok(Box::new(Expr::LetCc { name, body }))
+    }
+
+    fn throw_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let value = self.expr()?;
+        self.expect(TokenTag::To)?;
+        let cont = self.expr()?;
+
+        Ok(Box::new(Expr::Throw { value, cont }))
+    }
+
+    fn try_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let body = self.expr()?;
+        self.expect(TokenTag::Catch)?;
         self.expect(TokenTag::LeftParen)?;
-        let param = self.param()?;
+        let name = self.expect_identifer()?;
         self.expect(TokenTag::RightParen)?;
-        let proc_body = self.expr()?;
+        let handler = self.expr()?;
+
+        Ok(Box::new(Expr::Try {
+            body,
+            name,
+            handler,
+        }))
+    }
+
+    fn data_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let name = self.expect_identifer()?;
+        self.expect(TokenTag::Equal)?;
+        let mut variants = vec![self.variant()?];
+        while self.is_match(TokenTag::Bar)? {
+            variants.push(self.variant()?);
+        }
         self.expect(TokenTag::In)?;
-        let let_body = self.expr()?;
+        let body = self.expr()?;
 
-        Ok(Box::new(Expr::LetRec {
-            t_result,
+        Ok(Box::new(Expr::Data {
             name,
-            param,
-            proc_body,
-            let_body,
+            variants,
+            body,
         }))
     }
 
-    fn proc_expr(&mut self) -> ExprResult {
+    fn variant(&mut self) -> Result<Variant, String> {
+        let name = self.expect_identifer()?;
+        let mut t_fields = Vec::new();
+        if self.is_match(TokenTag::LeftParen)? {
+            t_fields.push(self.parse_type()?);
+            while self.is_match(TokenTag::Comma)? {
+                t_fields.push(self.parse_type()?);
+            }
+            self.expect(TokenTag::RightParen)?;
+        }
+
+        Ok(Variant { name, t_fields })
+    }
+
+    fn match_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let expr = self.expr()?;
+        self.expect(TokenTag::With)?;
+        let mut arms = Vec::new();
+        self.expect(TokenTag::Bar)?;
+        arms.push(self.arm()?);
+        while self.is_continued_by(TokenTag::Bar)? {
+            arms.push(self.arm()?);
+        }
+
+        Ok(Box::new(Expr::Match { expr, arms }))
+    }
+
+    fn arm(&mut self) -> Result<Arm, String> {
+        let constructor = self.expect_identifer()?;
+        let mut names = Vec::new();
+        if self.is_match(TokenTag::LeftParen)? {
+            names.push(self.expect_identifer()?);
+            while self.is_match(TokenTag::Comma)? {
+                names.push(self.expect_identifer()?);
+            }
+            self.expect(TokenTag::RightParen)?;
+        }
+        self.expect(TokenTag::Arrow)?;
+        let body = *self.expr()?;
+
+        Ok(Arm {
+            constructor,
+            names,
+            body,
+        })
+    }
+
+    fn record_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        // A record and a field's name can both start with an identifier, so
+        // an identifier names a field only if `=` follows it.
+        let record = if let TokenTag::Identifier(name) = &self.current.tag {
+            let name = name.clone();
+            self.advance()?;
+            if self.is_match(TokenTag::Equal)? {
+                let expr = *self.expr()?;
+                let mut fields = vec![(name, expr)];
+                while self.is_match(TokenTag::Comma)? {
+                    fields.push(self.field_init()?);
+                }
+                self.expect(TokenTag::RightBrace)?;
+                return Ok(Box::new(Expr::Record(fields)));
+            }
+            self.field_accesses(Box::new(Expr::Name(name)))?
+        } else {
+            self.expr()?
+        };
+        self.expect(TokenTag::With)?;
+        let mut fields = vec![self.field_init()?];
+        while self.is_match(TokenTag::Comma)? {
+            fields.push(self.field_init()?);
+        }
+        self.expect(TokenTag::RightBrace)?;
+
+        Ok(Box::new(Expr::Update {
+            record,
+            fields,
+            offsets: RefCell::new(Vec::new()),
+        }))
+    }
+
+    fn field_init(&mut self) -> Result<(String, Expr), String> {
+        let name = self.expect_identifer()?;
+        self.expect(TokenTag::Equal)?;
+        let expr = *self.expr()?;
+        Ok((name, expr))
+    }
+
+    fn substring(&mut self) -> ExprResult {
         self.advance()?;
         self.expect(TokenTag::LeftParen)?;
-        let param = self.param()?;
+        let string = self.expr()?;
+        self.expect(TokenTag::Comma)?;
+        let start = self.expr()?;
+        self.expect(TokenTag::Comma)?;
+        let end = self.expr()?;
         self.expect(TokenTag::RightParen)?;
-        let body = self.expr()?;
 
-        Ok(Box::new(Expr::Proc { param, body }))
+        Ok(Box::new(Expr::Substring { string, start, end }))
     }
 
-    fn call_expr(&mut self) -> ExprResult {
+    fn list_expr(&mut self) -> ExprResult {
         self.advance()?;
-        let proc = self.expr()?;
-        let arg = self.expr()?;
+        let mut elements = Vec::new();
+        if !self.is_match(TokenTag::RightBracket)? {
+            elements.push(*self.expr()?);
+            while self.is_match(TokenTag::Comma)? {
+                elements.push(*self.expr()?);
+            }
+            self.expect(TokenTag::RightBracket)?;
+        }
+
+        Ok(Box::new(Expr::List(elements)))
+    }
+
+    fn tuple_expr(&mut self, first: Expr) -> ExprResult {
+        let mut elements = vec![first];
+        while self.is_match(TokenTag::Comma)? {
+            elements.push(*self.expr()?);
+        }
         self.expect(TokenTag::RightParen)?;
 
-        Ok(Box::new(Expr::Call { proc, arg }))
+        Ok(Box::new(Expr::Tuple(elements)))
+    }
+
+    fn unpack_expr(&mut self) -> ExprResult {
+        self.advance()?;
+        let mut names = vec![self.expect_identifer()?];
+        self.expect(TokenTag::Comma)?;
+        names.push(self.expect_identifer()?);
+        while self.is_match(TokenTag::Comma)? {
+            names.push(self.expect_identifer()?);
+        }
+        self.expect(TokenTag::Equal)?;
+        let expr = self.expr()?;
+        self.expect(TokenTag::In)?;
+        let body = self.expr()?;
+
+        Ok(Box::new(Expr::Unpack { names, expr, body }))
+    }
+
+    fn params(&mut self) -> Result<Vec<Param>, String> {
+        self.expect(TokenTag::LeftParen)?;
+        let mut params = Vec::new();
+        if !self.is_match(TokenTag::RightParen)? {
+            loop {
+                let param = self.param()?;
+                params.push(param);
+                if self.is_match(TokenTag::RightParen)? {
+                    break;
+                }
+                self.expect(TokenTag::Comma)?;
+            }
+        }
+        Ok(params)
     }
 
     fn param(&mut self) -> Result<Param, String> {
+        let name = self.expect_identifer()?;
+        let t = if self.is_match(TokenTag::Colon)? {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        Ok(Param::new(name, t))
+    }
+
+    fn field_type(&mut self) -> Result<(String, Type), String> {
         let name = self.expect_identifer()?;
         self.expect(TokenTag::Colon)?;
         let t = self.parse_type()?;
-        Ok(Param::new(name, t))
+        Ok((name, t))
     }
 
     fn parse_type(&mut self) -> Result<Type, String> {
-        match self.current.tag {
+        match &self.current.tag {
             TokenTag::Int => {
                 self.advance()?;
                 Ok(Type::new_int())
@@ -211,15 +898,89 @@ impl<'a> Parser<'a> {
                 self.advance()?;
                 Ok(Type::new_bool())
             }
+            TokenTag::Unit => {
+                self.advance()?;
+                Ok(Type::new_unit())
+            }
+            TokenTag::String => {
+                self.advance()?;
+                Ok(Type::new_string())
+            }
+            TokenTag::Mutex => {
+                self.advance()?;
+                Ok(Type::new_mutex())
+            }
+            TokenTag::Ref => {
+                self.advance()?;
+                let t_referent = self.parse_type()?;
+                Ok(Type::new_ref(t_referent))
+            }
+            TokenTag::Identifier(name) => {
+                let name = name.clone();
+                self.advance()?;
+                Ok(Type::new_named(name))
+            }
+            TokenTag::From => {
+                let (module, name) = self.qualified()?;
+                Ok(Type::new_qualified(module, name))
+            }
+            TokenTag::LeftBrace => {
+                self.advance()?;
+                let mut t_fields = vec![self.field_type()?];
+                while self.is_match(TokenTag::Comma)? {
+                    t_fields.push(self.field_type()?);
+                }
+                self.expect(TokenTag::RightBrace)?;
+                for (i, (name, _)) in t_fields.iter().enumerate() {
+                    if t_fields[..i]
+                        .iter()
+                        .any(|(other_name, _)| other_name == name)
+                    {
+                        return Err(format!("record type has more than one field `{name}`"));
+                    }
+                }
+                Ok(Type::new_record(t_fields))
+            }
+            TokenTag::Cont => {
+                self.advance()?;
+                let t_value = self.parse_type()?;
+                Ok(Type::new_cont(t_value))
+            }
+            TokenTag::ListOf => {
+                self.advance()?;
+                let t_element = self.parse_type()?;
+                Ok(Type::new_list(t_element))
+            }
             TokenTag::LeftParen => {
                 self.advance()?;
-                let var_type = self.parse_type()?;
-                self.expect(TokenTag::Arrow)?;
-                let result_type = self.parse_type()?;
-                self.expect(TokenTag::RightParen)?;
-                Ok(Type::new_proc(var_type, result_type))
+                if self.is_match(TokenTag::Arrow)? {
+                    let result_type = self.parse_type()?;
+                    self.expect(TokenTag::RightParen)?;
+                    return Ok(Type::new_proc(Vec::new(), result_type));
+                }
+                let first_type = self.parse_type()?;
+                if self.current.tag == TokenTag::Star {
+                    let mut element_types = vec![first_type];
+                    while self.is_match(TokenTag::Star)? {
+                        element_types.push(self.parse_type()?);
+                    }
+                    self.expect(TokenTag::RightParen)?;
+                    Ok(Type::new_tuple(element_types))
+                } else {
+                    let mut param_types = vec![first_type];
+                    while self.is_match(TokenTag::Comma)? {
+                        param_types.push(self.parse_type()?);
+                    }
+                    self.expect(TokenTag::Arrow)?;
+                    let result_type = self.parse_type()?;
+                    self.expect(TokenTag::RightParen)?;
+                    Ok(Type::new_proc(param_types, result_type))
+                }
+            }
+            _ => {
+                let msg = format!("unexpected token `{}`", self.current.tag);
+                Err(self.unexpected(msg))
             }
-            _ => Err(format!("unexpected token `{}`", self.current.tag)),
         }
     }
 }
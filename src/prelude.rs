@@ -0,0 +1,35 @@
+//! The prelude, definitions written in letpl which are in scope in every
+//! program.
+
+use crate::ast::Def;
+use crate::parser;
+use crate::type_checking;
+use crate::types::Type;
+
+const SRC: &str = include_str!("prelude.let");
+
+/// The prelude's definitions, parsed and type checked once so every program
+/// evaluated can use them.
+pub struct Prelude {
+    /// The prelude's top-level definitions, in order.
+    pub defs: Vec<Def>,
+
+    /// The types of the names the prelude defines, in order.
+    pub t_defs: Vec<(String, Type)>,
+}
+
+impl Prelude {
+    /// Creates a prelude which defines nothing.
+    pub fn empty() -> Self {
+        Self {
+            defs: Vec::new(),
+            t_defs: Vec::new(),
+        }
+    }
+
+    pub fn load() -> Result<Self, String> {
+        let defs = parser::parse_prelude(SRC).map_err(|e| format!("prelude: {e}"))?;
+        let t_defs = type_checking::type_of_prelude(&defs).map_err(|e| format!("prelude: {e}"))?;
+        Ok(Self { defs, t_defs })
+    }
+}
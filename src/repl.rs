@@ -0,0 +1,294 @@
+//! The read-eval-print loop, run when letpl is given no script.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::path::Path;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+
+use crate::ast::Program;
+use crate::parser::{self, ParseError};
+use crate::prelude::Prelude;
+use crate::session::{self, Session, Timings};
+use crate::{compiler, eval, loader, print, print_warnings, read_file_eval, scanner};
+use crate::{EvalResult, Options};
+
+/// The prompt for the first line of an input.
+const PROMPT: &str = "> ";
+
+/// The prompt for each line of an input after its first.
+const CONTINUATION_PROMPT: &str = "... ";
+
+/// The REPL's meta-commands, each with its argument and what it does.
+const COMMANDS: &[(&str, &str, &str)] = &[
+    (":type", "e", "type checks e and prints its type"),
+    (":ast", "e", "prints the syntax tree e parses to"),
+    (
+        ":nameless",
+        "e",
+        "prints e's syntax tree once its names are resolved",
+    ),
+    (":bytecode", "e", "prints the ops e compiles to"),
+    (":load", "file", "evaluates the program in file"),
+    (
+        ":time",
+        "e",
+        "evaluates e and prints how long each phase took",
+    ),
+    (
+        ":reset",
+        "",
+        "starts a new session, in which only the prelude is in scope",
+    ),
+    (":help", "", "lists the commands"),
+];
+
+/// Completes the word before the cursor with a keyword or a name in scope, or
+/// with a meta-command at the start of an input, and tells when an input is
+/// whole.
+struct Completion {
+    names: Vec<String>,
+
+    /// The lines of the input before the one being typed.
+    earlier: String,
+
+    /// Whether the input was whole once the last line typed was added to it.
+    is_whole: Cell<bool>,
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        if before.starts_with(':') && !before.contains(char::is_whitespace) {
+            let commands = COMMANDS
+                .iter()
+                .map(|(command, _, _)| (*command).to_string())
+                .filter(|command| command.starts_with(before))
+                .collect();
+            return Ok((0, commands));
+        }
+
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !scanner::is_identifier_char(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &before[start..];
+        let mut words: Vec<String> = scanner::keywords()
+            .map(String::from)
+            .chain(self.names.iter().cloned())
+            .filter(|candidate| candidate.starts_with(word))
+            .collect();
+        words.sort();
+        words.dedup();
+        Ok((start, words))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {
+    // Each line is accepted by itself, whether or not it ends the input, so
+    // that the line after it is read with the continuation prompt.
+    fn validate(&self, ctx: &mut ValidationContext<'_>) -> rustyline::Result<ValidationResult> {
+        let src = format!("{}{}", self.earlier, ctx.input());
+        self.is_whole.set(is_whole(&src));
+        Ok(ValidationResult::Valid(None))
+    }
+}
+
+/// Tests if an input is whole. A program goes on over more lines for as long as
+/// it is the start of a program. Once it is a whole program, it goes on until
+/// a blank line if its next line could continue it.
+fn is_whole(src: &str) -> bool {
+    if src.trim_start().starts_with(':') {
+        return true;
+    }
+    match parser::parse(src) {
+        Err(ParseError::Incomplete(_)) => false,
+        Err(ParseError::Invalid(_)) => true,
+        Ok(_) => {
+            let ends_with_blank_line = src
+                .rsplit_once('\n')
+                .is_some_and(|(_, last)| last.trim().is_empty());
+            ends_with_blank_line || !parser::can_continue(src)
+        }
+    }
+}
+
+impl Helper for Completion {}
+
+/// Reads inputs and prints their values until the end of the input.
+pub fn repl(options: &Options, prelude: &Prelude, mut session: Session) {
+    let mut editor = match Editor::<Completion, DefaultHistory>::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return;
+        }
+    };
+    loop {
+        let names = session.names().map(String::from).collect();
+        editor.set_helper(Some(Completion {
+            names,
+            earlier: String::new(),
+            is_whole: Cell::new(true),
+        }));
+        let (src, is_last) = match read(&mut editor) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("error: {e}");
+                return;
+            }
+        };
+        if let Some(command) = src.trim().strip_prefix(':') {
+            if let Err(e) = run_command(command, options, prelude, &mut session) {
+                eprintln!("error: {e}");
+            }
+        } else if !src.trim().is_empty() {
+            let result = parse_eval(&src, options, &mut session, &mut Timings::new());
+            print(result);
+        }
+        if is_last {
+            return;
+        }
+    }
+}
+
+/// Reads a whole input, which may span lines, and whether the REPL's input
+/// ended after it.
+fn read(editor: &mut Editor<Completion, DefaultHistory>) -> Result<(String, bool), ReadlineError> {
+    let mut src = String::new();
+    loop {
+        let prompt = if src.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        if let Some(helper) = editor.helper_mut() {
+            helper.earlier.clone_from(&src);
+        }
+        match editor.readline(prompt) {
+            Ok(line) => {
+                src.push_str(&line);
+                src.push('\n');
+                if editor.helper().is_none_or(|helper| helper.is_whole.get()) {
+                    if !src.trim().is_empty() {
+                        editor.add_history_entry(src.trim_end())?;
+                    }
+                    return Ok((src, false));
+                }
+            }
+            // Ctrl-C abandons the input being typed.
+            Err(ReadlineError::Interrupted) => src.clear(),
+            Err(ReadlineError::Eof) => {
+                // The end of the input ends a whole program still waiting for
+                // a blank line, but may also cut off a program.
+                if let Err(e) = parser::parse(&src) {
+                    eprintln!("error: {e}");
+                    src.clear();
+                }
+                return Ok((src, true));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Runs a REPL meta-command, given without its `:` and followed by its
+/// argument.
+fn run_command(
+    command: &str,
+    options: &Options,
+    prelude: &Prelude,
+    session: &mut Session,
+) -> Result<(), Box<dyn Error>> {
+    let (name, arg) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    let arg = arg.trim();
+    match name {
+        "type" => {
+            let program = parse(arg, options)?;
+            let (t, warnings) = session.type_of(&program)?;
+            print_warnings(warnings);
+            println!("{t}");
+        }
+        "ast" => {
+            let program = parser::parse(arg)?;
+            println!("{program:#?}");
+        }
+        "nameless" => {
+            let program = parse(arg, options)?;
+            let nameless_program = session.resolve_names(&program)?;
+            println!("{nameless_program:#?}");
+        }
+        "bytecode" => {
+            let program = parse(arg, options)?;
+            let nameless_program = session.resolve_names(&program)?;
+            print!("{}", compiler::disassemble(&nameless_program)?);
+        }
+        "load" => {
+            let result = read_file_eval(Path::new(arg), options, session);
+            print(result);
+        }
+        "time" => {
+            let mut timings = Timings::new();
+            let result = parse_eval(arg, options, session, &mut timings);
+            print(result);
+            for (phase, duration) in timings {
+                println!("{phase}: {duration:?}");
+            }
+        }
+        "reset" => {
+            // A new session runs the prelude again, so no value made by an
+            // earlier input is kept.
+            *session = Session::new(prelude, options.quantum)?;
+        }
+        "help" => {
+            for (command, arg, about) in COMMANDS {
+                let usage = format!("{command} {arg}");
+                println!("{usage:<12} {about}");
+            }
+        }
+        _ => return Err(format!("unknown command `:{name}`, `:help` lists the commands").into()),
+    }
+    Ok(())
+}
+
+fn parse_eval(
+    src: &str,
+    options: &Options,
+    session: &mut Session,
+    timings: &mut Timings,
+) -> EvalResult {
+    let mut program = session::timed(timings, "parsing", || parser::parse(src))?;
+    session::timed(timings, "loading imports", || {
+        loader::load_imports(&mut program, None, &options.search_path)
+    })?;
+    eval(&program, session, timings)
+}
+
+/// Parses an input and loads its imports.
+fn parse(src: &str, options: &Options) -> Result<Program, Box<dyn Error>> {
+    let mut program = parser::parse(src)?;
+    loader::load_imports(&mut program, None, &options.search_path)?;
+    Ok(program)
+}
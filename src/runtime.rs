@@ -1,10 +1,17 @@
 //! A stack-based VM.
 
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::fmt;
+use std::mem;
 use std::rc::Rc;
 
 use crate::offset::{Capture, CaptureOffset, StackOffset};
 
+/// The number of ops a thread runs before the scheduler switches to the next
+/// ready thread, unless a different quantum is given to `run`.
+pub const DEFAULT_QUANTUM: usize = 100;
+
 /// An offset in a VM program.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Address(pub usize);
@@ -47,12 +54,172 @@ impl fmt::Display for Procedure {
     }
 }
 
+/// An immutable list. A list made by `cons` shares its tail with the list it
+/// was made from.
+pub enum List {
+    Empty,
+    Cons { head: Value, tail: Rc<List> },
+}
+
+impl List {
+    pub fn empty() -> Rc<List> {
+        Rc::new(List::Empty)
+    }
+}
+
+impl Drop for List {
+    // A long list would overflow the stack if each tail were dropped by
+    // dropping its own tail, so the tails no other list shares are unlinked
+    // one at a time.
+    fn drop(&mut self) {
+        let List::Cons { tail, .. } = self else {
+            return;
+        };
+        let mut tail = mem::replace(tail, List::empty());
+        while let Ok(mut list) = Rc::try_unwrap(tail) {
+            let List::Cons { tail: next, .. } = &mut list else {
+                return;
+            };
+            tail = mem::replace(next, List::empty());
+        }
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        let mut list = self;
+        let mut is_first = true;
+        while let List::Cons { head, tail } = list {
+            if !is_first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{head}")?;
+            is_first = false;
+            list = tail;
+        }
+        write!(f, "]")
+    }
+}
+
+/// A snapshot of the VM's state from which execution can be resumed. Resuming
+/// a continuation pushes a value onto its stack and continues at its `next_op`.
+pub struct Continuation {
+    next_op: Address,
+    stack: Vec<Value>,
+    call_stack: Vec<Frame>,
+    stack_base: StackOffset,
+    captures: Rc<Vec<Value>>,
+}
+
+impl fmt::Display for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<cont {}>", self.next_op)
+    }
+}
+
+/// A class's methods, including inherited ones, and the names of its objects'
+/// fields.
+pub struct Class {
+    name: Rc<str>,
+    fields: Vec<Rc<str>>,
+    methods: Vec<(Rc<str>, Value)>,
+}
+
+impl Class {
+    fn method(&self, name: &str) -> Result<&Value, String> {
+        self.methods
+            .iter()
+            .find(|(method_name, _)| method_name.as_ref() == name)
+            .map(|(_, method)| method)
+            .ok_or_else(|| format!("class `{}` has no method `{name}`", self.name))
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+/// An object of a class, with a value for each of the class's fields which has
+/// been set.
+pub struct Object {
+    class: Rc<Class>,
+    fields: RefCell<Vec<Option<Value>>>,
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<object {}>", self.class.name)
+    }
+}
+
+/// A lock which is held by at most one thread at a time, with the threads
+/// waiting for it in the order they started waiting.
+pub struct Mutex {
+    is_locked: bool,
+    waiting: VecDeque<Thread>,
+}
+
+/// A value made by a constructor of a variant type.
+pub struct Variant {
+    name: Rc<str>,
+    tag: usize,
+    fields: Vec<Value>,
+}
+
+impl Drop for Variant {
+    // A long chain of variants, such as a list declared with `data`, would
+    // overflow the stack if each were dropped by dropping its fields, so the
+    // fields of the variants no other value shares are dropped from a
+    // worklist.
+    fn drop(&mut self) {
+        let mut fields = mem::take(&mut self.fields);
+        while let Some(field) = fields.pop() {
+            if let Value::Variant(variant) = field {
+                if let Ok(mut variant) = Rc::try_unwrap(variant) {
+                    fields.append(&mut variant.fields);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.fields.is_empty() {
+            write!(f, "(")?;
+            for (i, field) in self.fields.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{field}")?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
 /// Values to which expressions evalutate.
 #[derive(Clone)]
 pub enum Value {
     Integer(i64),
     Boolean(bool),
+    String(Rc<str>),
     Procedure(Rc<Procedure>),
+    Ref(Rc<RefCell<Value>>),
+    List(Rc<List>),
+    Tuple(Rc<Vec<Value>>),
+    Record(Rc<Vec<(Rc<str>, Value)>>),
+    Variant(Rc<Variant>),
+    Continuation(Rc<Continuation>),
+    Mutex(Rc<RefCell<Mutex>>),
+    Class(Rc<Class>),
+    Object(Rc<Object>),
+    Unit,
 }
 
 impl Value {
@@ -70,12 +237,91 @@ impl Value {
         }
     }
 
+    pub fn as_string(&self) -> Result<&Rc<str>, String> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(String::from("value is not a string")),
+        }
+    }
+
     pub fn as_proc(&self) -> Result<&Procedure, String> {
         match self {
             Value::Procedure(p) => Ok(p),
             _ => Err(String::from("value is not a procedure")),
         }
     }
+
+    pub fn as_ref(&self) -> Result<&RefCell<Value>, String> {
+        match self {
+            Value::Ref(r) => Ok(r),
+            _ => Err(String::from("value is not a reference")),
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&Rc<List>, String> {
+        match self {
+            Value::List(l) => Ok(l),
+            _ => Err(String::from("value is not a list")),
+        }
+    }
+
+    pub fn as_tuple(&self) -> Result<&[Value], String> {
+        match self {
+            Value::Tuple(elements) => Ok(elements),
+            _ => Err(String::from("value is not a tuple")),
+        }
+    }
+
+    pub fn as_record(&self) -> Result<&[(Rc<str>, Value)], String> {
+        match self {
+            Value::Record(fields) => Ok(fields),
+            _ => Err(String::from("value is not a record")),
+        }
+    }
+
+    pub fn as_variant(&self) -> Result<&Variant, String> {
+        match self {
+            Value::Variant(v) => Ok(v),
+            _ => Err(String::from("value is not a variant")),
+        }
+    }
+
+    pub fn as_cont(&self) -> Result<&Continuation, String> {
+        match self {
+            Value::Continuation(k) => Ok(k),
+            _ => Err(String::from("value is not a continuation")),
+        }
+    }
+
+    pub fn as_mutex(&self) -> Result<&RefCell<Mutex>, String> {
+        match self {
+            Value::Mutex(m) => Ok(m),
+            _ => Err(String::from("value is not a mutex")),
+        }
+    }
+
+    pub fn as_class(&self) -> Result<&Rc<Class>, String> {
+        match self {
+            Value::Class(c) => Ok(c),
+            _ => Err(String::from("value is not a class")),
+        }
+    }
+
+    pub fn as_object(&self) -> Result<&Object, String> {
+        match self {
+            Value::Object(o) => Ok(o),
+            _ => Err(String::from("value is not an object")),
+        }
+    }
+
+    pub fn equals(&self, other: &Value) -> Result<bool, String> {
+        match (self, other) {
+            (Value::Integer(x1), Value::Integer(x2)) => Ok(x1 == x2),
+            (Value::Boolean(b1), Value::Boolean(b2)) => Ok(b1 == b2),
+            (Value::String(s1), Value::String(s2)) => Ok(s1 == s2),
+            _ => Err(String::from("values cannot be compared for equality")),
+        }
+    }
 }
 
 impl fmt::Display for Value {
@@ -83,7 +329,36 @@ impl fmt::Display for Value {
         match self {
             Value::Integer(x) => write!(f, "{x}"),
             Value::Boolean(b) => write!(f, "{b}"),
+            Value::String(s) => write!(f, "{s:?}"),
             Value::Procedure(p) => write!(f, "{p}"),
+            Value::Ref(r) => write!(f, "<ref {}>", r.borrow()),
+            Value::List(l) => write!(f, "{l}"),
+            Value::Record(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{name} = {value}")?;
+                }
+                write!(f, "}}")
+            }
+            Value::Variant(v) => write!(f, "{v}"),
+            Value::Continuation(k) => write!(f, "{k}"),
+            Value::Mutex(_) => write!(f, "<mutex>"),
+            Value::Class(c) => write!(f, "{c}"),
+            Value::Object(o) => write!(f, "{o}"),
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                write!(f, ")")
+            }
+            Value::Unit => write!(f, "()"),
         }
     }
 }
@@ -97,23 +372,87 @@ impl fmt::Debug for Value {
 /// The VM's operations.
 #[derive(Debug)]
 pub enum Op {
+    /// Pop two numbers from the stack, add them, and push the sum onto the
+    /// stack.
+    Add,
+
     /// Pop a Boolean from the stack. If the value is false then halt execution
     /// and include the line number in the error message.
     Assert {
         line: usize,
     },
 
-    /// Call a procedure. Call expects two values at the on the stack: at the
-    /// top an argument and next a procedure. Save he current op index and
-    /// environment to the call stack, then set the instruction index and
-    /// environment to the procedure's start and environment, respectively. The
-    /// procedure's code must pop the argument and procedure from the stack.
-    Call,
+    /// Pop a list from the stack and push its first element.
+    Car,
+
+    /// Pop a list from the stack and push the list of its elements after the
+    /// first.
+    Cdr,
+
+    /// Pop two strings from the stack and push the first pushed followed by the
+    /// second.
+    Concat,
+
+    /// Pop a list and then a value from the stack and push a list with the
+    /// value in front of the popped list.
+    Cons,
+
+    /// Replace a local with a reference to it, so the local can be assigned
+    /// and the assignment seen by every closure which captures it.
+    BoxLocal(StackOffset),
+
+    /// Call a procedure with a given number of arguments. Call expects the
+    /// arguments on the top of the stack with the first argument deepest and
+    /// the procedure below them. Save he current op index and environment to
+    /// the call stack, then set the instruction index and environment to the
+    /// procedure's start and environment, respectively. The procedure's code
+    /// must pop the arguments and procedure from the stack.
+    Call(usize),
+
+    /// Pop a procedure from the stack and call it with one argument, a
+    /// continuation which resumes at the next op with the stack as it is after
+    /// the procedure is popped.
+    CallCc,
 
     /// Pop two numbers from the stack, subtract them, and push the difference
     /// onto the stack.
     Diff,
 
+    /// Pop a reference from the stack and push the value it refers to.
+    DeRef,
+
+    /// Pop two numbers from the stack, divide the first pushed by the second,
+    /// and push the quotient onto the stack.
+    Divide,
+
+    /// Pop two values from the stack. Push `true` if they are equal, otherwise
+    /// push `false`.
+    Equal,
+
+    /// Pop a record from the stack and push its field at a given offset.
+    GetField(usize),
+
+    /// Pop an object from the stack and push its field at a given offset.
+    GetObjectField(usize),
+
+    /// Pop two numbers from the stack. Push `true` if the first pushed is
+    /// greater than the second, otherwise push `false`.
+    Greater,
+
+    /// Pop a number from the stack and push its decimal string.
+    IntToString,
+
+    /// Pop a list from the stack. Push `true` if it is empty, otherwise push
+    /// `false`.
+    IsNull,
+
+    /// Pop two numbers from the stack. Push `true` if the first pushed is
+    /// greater than or equal to the second, otherwise push `false`.
+    GreaterEqual,
+
+    /// Finish the thread, as if it had run past the program's last op.
+    Halt,
+
     /// Pop a number from the stack. If the popped value is zero then push
     /// `true` onto the stack, otherwise push `false`.
     IsZero,
@@ -125,13 +464,97 @@ pub enum Op {
     /// an index.
     JumpTrue(Address),
 
+    /// Pop two numbers from the stack. Push `true` if the first pushed is less
+    /// than the second, otherwise push `false`.
+    Less,
+
+    /// Pop two numbers from the stack. Push `true` if the first pushed is less
+    /// than or equal to the second, otherwise push `false`.
+    LessEqual,
+
+    /// Pop a string from the stack and push its number of characters.
+    Length,
+
+    /// Pop a given number of values from the stack and push a list of them,
+    /// with the first pushed value first.
+    MakeList(usize),
+
+    /// Pop a value for each of the given field names, the first name's value
+    /// deepest, and push a record of them. The record's fields are sorted by
+    /// name, matching the offsets the type checker gives them.
+    MakeRecord(Vec<Rc<str>>),
+
+    /// Pop a given number of values from the stack and push a variant with a
+    /// given constructor name and tag whose fields are the values, with the
+    /// first pushed value first.
+    MakeVariant {
+        name: Rc<str>,
+        tag: usize,
+        count: usize,
+    },
+
+    /// Pop a variant from the stack and halt execution because no `match` arm
+    /// is for its constructor.
+    NoMatch,
+
+    /// Pop a given number of values from the stack and push a tuple of them,
+    /// with the first pushed value first.
+    MakeTuple(usize),
+
     /// Make a procedure using a start index and the environment. Push the
     /// procedure onto the stack.
     MakeProc(Address, Vec<Capture>),
 
+    /// Pop a procedure for each of the given method names and then, if the
+    /// class has one, a superclass from the stack. Push a class with the
+    /// superclass's methods, replaced by or followed by the popped ones.
+    MakeClass {
+        name: Rc<str>,
+        fields: Vec<Rc<str>>,
+        methods: Vec<Rc<str>>,
+        has_superclass: bool,
+    },
+
+    /// Push a continuation which resumes at a given address with the stack as
+    /// it is before the continuation is pushed.
+    MakeCont(Address),
+
+    /// Pop two numbers from the stack, multiply them, and push the product
+    /// onto the stack.
+    Multiply,
+
     /// Negates the top of the stack.
     Negate,
 
+    /// Push a new, unlocked mutex.
+    NewMutex,
+
+    /// Pop a value from the stack and push a new reference to it.
+    NewRef,
+
+    /// Make an object of the class below a given number of arguments on the
+    /// stack, replacing the class with the object. Then call the class's
+    /// `initialize` method with the object and the arguments, or push unit if
+    /// it has none.
+    NewObject(usize),
+
+    /// Pop a Boolean from the stack and push its negation.
+    Not,
+
+    /// Pop two values from the stack. Push `true` if they are not equal,
+    /// otherwise push `false`.
+    NotEqual,
+
+    /// Pop a value from the stack and discard it.
+    Pop,
+
+    /// Pop a value from the stack, then pop a given number of values and
+    /// discard them, then push the first value back onto the stack.
+    PopBelow(usize),
+
+    /// Pop the handler frame pushed by `PushHandler` from the call stack.
+    PopHandler,
+
     /// Pushes a captured value onto the stack.
     PushCapture(CaptureOffset),
 
@@ -140,21 +563,116 @@ pub enum Op {
     /// Pushes a environment binding onto the stack.
     PushLocal(StackOffset),
 
+    /// Push a handler frame onto the call stack. If an exception is raised
+    /// before the frame is popped, the stack is unwound to its current height,
+    /// the exception's value is pushed, and execution continues at the given
+    /// address.
+    PushHandler(Address),
+
     /// Push a value onto the stack.
     PushValue(Value),
 
+    /// Pop a value from the stack and raise it as an exception. Frames are
+    /// popped from the call stack until a handler frame is found, and it is an
+    /// error if there is none.
+    Raise,
+
+    /// Pop two numbers from the stack, divide the first pushed by the second,
+    /// and push the remainder onto the stack.
+    Remainder,
+
     /// Return from a procedure. Pop the op index and environment from the call
     /// stack. For the return value, the procedure's code must have left one
     /// value on the stack.
     Return,
 
-    TailCall,
+    /// Pop a value and then a reference from the stack. Make the reference
+    /// refer to the value and push unit onto the stack.
+    SetRef,
+
+    /// Call the method of the object below a given number of arguments on the
+    /// stack, found by name in the object's class, with the object and the
+    /// arguments.
+    Send {
+        method: Rc<str>,
+        arity: usize,
+    },
+
+    /// Pop a value and then an object from the stack. Assign the value to the
+    /// object's field at a given offset and push unit.
+    SetObjectField(usize),
+
+    /// Pop a class from the stack, then call its method with a given name as
+    /// `Send` does.
+    SuperSend {
+        method: Rc<str>,
+        arity: usize,
+    },
+
+    /// Pop a mutex from the stack and push unit. If a thread is waiting on the
+    /// mutex, the first one holds the mutex and is made ready to run,
+    /// otherwise the mutex is unlocked.
+    Signal,
+
+    /// Pop a procedure from the stack and push unit. Add a thread which calls
+    /// the procedure with no arguments to the end of the run queue.
+    Spawn,
+
+    /// Pop a decimal string from the stack and push the number it represents.
+    StringToInt,
+
+    /// Pop an end index, a start index and a string from the stack and push
+    /// the string's characters from the start up to but not including the
+    /// end.
+    Substring,
+
+    /// Call a procedure with a given number of arguments in place of the
+    /// current procedure's frame. The stack is expected to look as it does for
+    /// `Call`.
+    TailCall(usize),
+
+    /// Push `true` if the variant on the top of the stack has a given tag,
+    /// otherwise push `false`. The variant is left on the stack.
+    TestTag(usize),
+
+    /// Pop a continuation and then a value from the stack. Restore the stack
+    /// and call stack saved in the continuation, push the value and continue
+    /// at the continuation's address.
+    Throw,
+
+    /// Pop a tuple with a given number of elements from the stack and push its
+    /// elements, the first element deepest.
+    Unpack(usize),
+
+    /// Pop a variant from the stack and push its fields, the first field
+    /// deepest.
+    UnpackVariant,
+
+    /// Pop a value for each of the given offsets, the first offset's value
+    /// deepest, and then a record. Push a copy of the record with the fields
+    /// at the offsets changed to the values.
+    UpdateRecord(Vec<usize>),
+
+    /// Pop a mutex from the stack and push unit. If the mutex is unlocked,
+    /// lock it, otherwise add the thread to the mutex's waiting threads and
+    /// run the next thread in the run queue.
+    Wait,
+
+    /// Push unit, then add the thread to the end of the run queue and run the
+    /// thread at its front.
+    Yield,
 }
 
+#[derive(Clone)]
 struct Frame {
     next_op: Address,
     stack_base: StackOffset,
     captures: Rc<Vec<Value>>,
+
+    /// For a handler frame, the height to unwind the stack to when an
+    /// exception is raised. A handler frame's `next_op` is its handler's
+    /// start.
+    handler_stack_top: Option<StackOffset>,
 }
 
 impl Frame {
@@ -163,21 +681,159 @@ impl Frame {
             next_op,
             stack_base,
             captures,
+            handler_stack_top: None,
+        }
+    }
+
+    fn new_handler(
+        handler: Address,
+        stack_base: StackOffset,
+        captures: Rc<Vec<Value>>,
+        stack_top: StackOffset,
+    ) -> Self {
+        Self {
+            next_op: handler,
+            stack_base,
+            captures,
+            handler_stack_top: Some(stack_top),
         }
     }
 }
 
+/// The state of a thread of execution.
+struct Thread {
+    stack: ValueStack,
+    call_stack: Vec<Frame>,
+    next_op: Address,
+    stack_base: StackOffset,
+    captures: Rc<Vec<Value>>,
+
+    /// Whether this is the thread which runs the program's main expression.
+    is_main: bool,
+}
+
+impl Thread {
+    /// Creates the thread which runs a program from a given op, with a stack
+    /// which starts with the globals of earlier programs.
+    fn new_main(start: Address, globals: Vec<Value>) -> Self {
+        Self {
+            stack: ValueStack { stack: globals },
+            call_stack: Vec::new(),
+            next_op: start,
+            stack_base: StackOffset(0),
+            captures: Rc::new(Vec::new()),
+            is_main: true,
+        }
+    }
+
+    /// Creates a thread, spawned by this one, which calls a procedure with no
+    /// arguments. When the procedure returns, the thread continues at `end`,
+    /// the end of the program, and so finishes.
+    fn spawn(&self, proc: Value, end: Address) -> Result<Self, String> {
+        let (start, captures) = {
+            let p = proc.as_proc()?;
+            (p.start, Rc::clone(&p.captures))
+        };
+
+        // Globals are found at fixed offsets in the stack, so the new thread's
+        // stack starts with a copy of this one's. Assigned globals are boxed,
+        // so the threads share them.
+        let mut stack = ValueStack {
+            stack: self.stack.stack.clone(),
+        };
+        let stack_base = StackOffset(stack.len());
+        stack.push(proc);
+        let finish_frame = Frame::new(end, StackOffset(0), Rc::new(Vec::new()));
+
+        Ok(Self {
+            stack,
+            call_stack: vec![finish_frame],
+            next_op: start,
+            stack_base,
+            captures,
+            is_main: false,
+        })
+    }
+
+    /// Calls the procedure below a given number of arguments on the stack.
+    fn call(&mut self, arity: usize) -> Result<(), String> {
+        let calling_frame = Frame::new(self.next_op, self.stack_base, Rc::clone(&self.captures));
+        self.call_stack.push(calling_frame);
+
+        self.stack_base = StackOffset(self.stack.len() - arity - 1);
+
+        let p = self
+            .stack
+            .value_at(self.stack_base, StackOffset(0))
+            .as_proc()?;
+
+        self.next_op = p.start;
+        self.captures = Rc::clone(&p.captures);
+        Ok(())
+    }
+
+    /// Captures a continuation which resumes at a given address with the
+    /// thread's stacks as they are now.
+    fn continuation(&self, next_op: Address) -> Continuation {
+        Continuation {
+            next_op,
+            stack: self.stack.stack.clone(),
+            call_stack: self.call_stack.clone(),
+            stack_base: self.stack_base,
+            captures: Rc::clone(&self.captures),
+        }
+    }
+}
+
+/// A round-robin scheduler. Threads are run in the order they become ready,
+/// each for at most a quantum of ops, so scheduling is deterministic.
+struct Scheduler {
+    ready: VecDeque<Thread>,
+    quantum: usize,
+    ticks: usize,
+}
+
+impl Scheduler {
+    fn new(quantum: usize) -> Self {
+        Self {
+            ready: VecDeque::new(),
+            quantum,
+            ticks: 0,
+        }
+    }
+
+    /// Counts an op run by the current thread, giving `true` if its quantum
+    /// has run out.
+    fn tick(&mut self) -> bool {
+        self.ticks += 1;
+        self.ticks > self.quantum
+    }
+
+    /// Adds a thread to the end of the run queue.
+    fn make_ready(&mut self, thread: Thread) {
+        self.ready.push_back(thread);
+    }
+
+    /// Takes the next thread to run from the front of the run queue.
+    fn next(&mut self) -> Option<Thread> {
+        self.ticks = 0;
+        self.ready.pop_front()
+    }
+
+    /// Puts a thread at the end of the run queue and takes the next thread to
+    /// run, which is the same thread if no other thread is ready.
+    fn switch(&mut self, thread: Thread) -> Thread {
+        self.make_ready(thread);
+        self.next().expect("run queue has a thread")
+    }
+}
+
 /// A stack of Value objects.
 struct ValueStack {
     stack: Vec<Value>,
 }
 
 impl ValueStack {
-    fn new() -> Self {
-        let stack = Vec::new();
-        Self { stack }
-    }
-
     fn len(&self) -> usize {
         self.stack.len()
     }
@@ -190,6 +846,16 @@ impl ValueStack {
         }
     }
 
+    /// Pop a given number of values, giving them in the order they were
+    /// pushed.
+    fn pop_n(&mut self, n: usize) -> Result<Vec<Value>, String> {
+        if n > self.stack.len() {
+            return Err(String::from("stack underflow"));
+        }
+        let values = self.stack.split_off(self.stack.len() - n);
+        Ok(values)
+    }
+
     fn pop_bool(&mut self) -> Result<bool, String> {
         self.pop()?.as_bool()
     }
@@ -198,11 +864,26 @@ impl ValueStack {
         self.pop()?.as_int()
     }
 
+    /// Gives the value below a given number of values on the top of the stack.
+    fn peek(&self, depth: usize) -> &Value {
+        &self.stack[self.stack.len() - depth - 1]
+    }
+
+    /// Puts a value below a given number of values on the top of the stack.
+    fn insert_below(&mut self, depth: usize, value: Value) {
+        self.stack.insert(self.stack.len() - depth, value);
+    }
+
+    /// Replace the stack's values with a given stack's.
+    fn restore(&mut self, stack: Vec<Value>) {
+        self.stack = stack;
+    }
+
     fn pop_to(&mut self, base: StackOffset) -> Result<(), String> {
         let StackOffset(base) = base;
         let top = self.stack.len();
         for _ in base..top {
-            if let None = self.stack.pop() {
+            if self.stack.pop().is_none() {
                 return Err(String::from("stack underflow"));
             }
         }
@@ -211,7 +892,12 @@ impl ValueStack {
     }
 
     fn push(&mut self, value: Value) {
-        self.stack.push(value)
+        self.stack.push(value);
+    }
+
+    fn set_value_at(&mut self, base: StackOffset, offset: StackOffset, value: Value) {
+        let StackOffset(absolute_offset) = base + offset;
+        self.stack[absolute_offset] = value;
     }
 
     fn value_at(&self, base: StackOffset, offset: StackOffset) -> &Value {
@@ -220,136 +906,634 @@ impl ValueStack {
     }
 }
 
-/// Run a VM program returning the final value on the stack.
-pub fn run(program: &[Op]) -> Result<Value, String> {
-    let mut stack = ValueStack::new();
-    let mut call_stack = Vec::<Frame>::new();
+/// Run a VM program from a given op, with the main thread's stack starting
+/// with the globals of earlier programs. Returns the final value on the main
+/// thread's stack and the values left below it, which start with the globals.
+/// The program's threads are switched after every `quantum` ops.
+#[allow(clippy::too_many_lines)]
+pub fn run(
+    program: &[Op],
+    start: Address,
+    globals: Vec<Value>,
+    quantum: usize,
+) -> Result<(Value, Vec<Value>), String> {
+    let end = Address(program.len());
+    let mut scheduler = Scheduler::new(quantum);
+    let mut thread = Thread::new_main(start, globals);
+    let mut main_value = None;
+
+    loop {
+        if scheduler.tick() {
+            thread = scheduler.switch(thread);
+        }
 
-    let mut next_op = Address(0);
-    let mut stack_base = StackOffset(0);
-    let mut captures = Rc::new(Vec::<Value>::new());
+        let Some(op) = thread.next_op.lookup(program) else {
+            // The thread has finished, so run the next one.
+            let value = thread.stack.pop()?;
+            if thread.is_main {
+                main_value = Some((value, mem::take(&mut thread.stack.stack)));
+            }
+            match scheduler.next() {
+                Some(next_thread) => {
+                    thread = next_thread;
+                    continue;
+                }
+                None => return finish(main_value),
+            }
+        };
 
-    while let Some(op) = next_op.lookup(program) {
         match op {
             Op::Assert { line } => {
-                if !stack.pop_bool()? {
+                if !thread.stack.pop_bool()? {
                     let msg = format!("Assert at line {line}");
-                    return Err(msg.to_string());
+                    return Err(msg.clone());
                 }
             }
 
-            Op::Call => {
-                let calling_frame = Frame::new(next_op, stack_base, captures);
-                call_stack.push(calling_frame);
+            Op::BoxLocal(offset) => {
+                let v = thread.stack.value_at(thread.stack_base, *offset).clone();
+                let reference = Value::Ref(Rc::new(RefCell::new(v)));
+                thread
+                    .stack
+                    .set_value_at(thread.stack_base, *offset, reference);
+            }
+
+            Op::Call(arity) => {
+                thread.call(*arity)?;
+            }
 
-                stack_base = StackOffset(stack.len() - 2);
+            Op::CallCc => {
+                let proc = thread.stack.pop()?;
+                let cont = thread.continuation(thread.next_op);
+                thread.stack.push(proc);
+                thread.stack.push(Value::Continuation(Rc::new(cont)));
+                thread.call(1)?;
+            }
 
-                let p = stack.value_at(stack_base, StackOffset(0)).as_proc()?;
+            Op::MakeCont(address) => {
+                let cont = thread.continuation(*address);
+                thread.stack.push(Value::Continuation(Rc::new(cont)));
+            }
 
-                next_op = p.start;
-                captures = Rc::clone(&p.captures);
+            Op::Throw => {
+                let cont = thread.stack.pop()?;
+                let value = thread.stack.pop()?;
+                let cont = cont.as_cont()?;
+                thread.stack.restore(cont.stack.clone());
+                thread.stack.push(value);
+                thread.call_stack.clone_from(&cont.call_stack);
+                thread.next_op = cont.next_op;
+                thread.stack_base = cont.stack_base;
+                thread.captures = Rc::clone(&cont.captures);
+            }
+
+            Op::Add => {
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                let v = Value::Integer(checked(x1.checked_add(x2))?);
+                thread.stack.push(v);
             }
 
             Op::Diff => {
-                let x2 = stack.pop_int()?;
-                let x1 = stack.pop_int()?;
-                let v = Value::Integer(x1 - x2);
-                stack.push(v);
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                let v = Value::Integer(checked(x1.checked_sub(x2))?);
+                thread.stack.push(v);
+            }
+
+            Op::DeRef => {
+                let reference = thread.stack.pop()?;
+                let v = reference.as_ref()?.borrow().clone();
+                thread.stack.push(v);
+            }
+
+            Op::Divide => {
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                let v = Value::Integer(checked_division(x1, x2, i64::checked_div)?);
+                thread.stack.push(v);
+            }
+
+            Op::Equal => {
+                let v2 = thread.stack.pop()?;
+                let v1 = thread.stack.pop()?;
+                let v = Value::Boolean(v1.equals(&v2)?);
+                thread.stack.push(v);
+            }
+
+            Op::Greater => {
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                thread.stack.push(Value::Boolean(x1 > x2));
+            }
+
+            Op::GreaterEqual => {
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                thread.stack.push(Value::Boolean(x1 >= x2));
+            }
+
+            Op::Halt => {
+                // The ops of earlier programs come before this one's, so a
+                // thread halting there was resumed by a continuation made by
+                // an earlier program, which would finish that program instead.
+                if thread.next_op.0 <= start.0 {
+                    return Err(String::from(
+                        "a continuation made by an earlier program cannot finish this one",
+                    ));
+                }
+                thread.next_op = end;
             }
 
             Op::IsZero => {
-                let x = stack.pop_int()?;
+                let x = thread.stack.pop_int()?;
                 let v = Value::Boolean(x == 0);
-                stack.push(v);
+                thread.stack.push(v);
             }
 
             Op::Jump(address) => {
-                next_op = *address;
+                thread.next_op = *address;
             }
 
             Op::JumpTrue(address) => {
-                if stack.pop_bool()? {
-                    next_op = *address;
+                if thread.stack.pop_bool()? {
+                    thread.next_op = *address;
                 }
             }
 
+            Op::Less => {
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                thread.stack.push(Value::Boolean(x1 < x2));
+            }
+
+            Op::LessEqual => {
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                thread.stack.push(Value::Boolean(x1 <= x2));
+            }
+
+            Op::Concat => {
+                let s2 = thread.stack.pop()?;
+                let s1 = thread.stack.pop()?;
+                let s = format!("{}{}", s1.as_string()?, s2.as_string()?);
+                thread.stack.push(Value::String(Rc::from(s)));
+            }
+
+            Op::Length => {
+                let s = thread.stack.pop()?;
+                let length = s.as_string()?.chars().count();
+                let length = i64::try_from(length).map_err(|_| "integer overflow")?;
+                thread.stack.push(Value::Integer(length));
+            }
+
+            Op::Substring => {
+                let end = thread.stack.pop_int()?;
+                let start = thread.stack.pop_int()?;
+                let s = thread.stack.pop()?;
+                let s = s.as_string()?;
+                let length = s.chars().count();
+                let range = usize::try_from(start).ok().zip(usize::try_from(end).ok());
+                let Some((start, end)) =
+                    range.filter(|&(start, end)| start <= end && end <= length)
+                else {
+                    return Err(format!(
+                        "substring({start}, {end}) is out of range for a string of length {length}"
+                    ));
+                };
+                let substring: String = s.chars().skip(start).take(end - start).collect();
+                thread.stack.push(Value::String(Rc::from(substring)));
+            }
+
+            Op::IntToString => {
+                let x = thread.stack.pop_int()?;
+                thread.stack.push(Value::String(Rc::from(x.to_string())));
+            }
+
+            Op::StringToInt => {
+                let s = thread.stack.pop()?;
+                let s = s.as_string()?;
+                let x = s
+                    .parse()
+                    .map_err(|_| format!("{s:?} cannot be converted to an integer"))?;
+                thread.stack.push(Value::Integer(x));
+            }
+
+            Op::MakeList(count) => {
+                let elements = thread.stack.pop_n(*count)?;
+                let list = elements
+                    .into_iter()
+                    .rev()
+                    .fold(List::empty(), |tail, head| {
+                        Rc::new(List::Cons { head, tail })
+                    });
+                thread.stack.push(Value::List(list));
+            }
+
+            Op::Cons => {
+                let tail = Rc::clone(thread.stack.pop()?.as_list()?);
+                let head = thread.stack.pop()?;
+                thread
+                    .stack
+                    .push(Value::List(Rc::new(List::Cons { head, tail })));
+            }
+
+            Op::Car => match thread.stack.pop()?.as_list()?.as_ref() {
+                List::Cons { head, .. } => thread.stack.push(head.clone()),
+                List::Empty => return Err(String::from("car() of an empty list")),
+            },
+
+            Op::Cdr => match thread.stack.pop()?.as_list()?.as_ref() {
+                List::Cons { tail, .. } => thread.stack.push(Value::List(Rc::clone(tail))),
+                List::Empty => return Err(String::from("cdr() of an empty list")),
+            },
+
+            Op::IsNull => {
+                let is_null = matches!(thread.stack.pop()?.as_list()?.as_ref(), List::Empty);
+                thread.stack.push(Value::Boolean(is_null));
+            }
+
+            Op::MakeVariant { name, tag, count } => {
+                let fields = thread.stack.pop_n(*count)?;
+                let variant = Variant {
+                    name: Rc::clone(name),
+                    tag: *tag,
+                    fields,
+                };
+                thread.stack.push(Value::Variant(Rc::new(variant)));
+            }
+
+            Op::NoMatch => {
+                let value = thread.stack.pop()?;
+                let variant = value.as_variant()?;
+                return Err(format!("no `match` arm for `{}`", variant.name));
+            }
+
+            Op::PopBelow(count) => {
+                let v = thread.stack.pop()?;
+                let _ = thread.stack.pop_n(*count)?;
+                thread.stack.push(v);
+            }
+
+            Op::TestTag(tag) => {
+                let v = thread.stack.pop()?;
+                let is_tag = v.as_variant()?.tag == *tag;
+                thread.stack.push(v);
+                thread.stack.push(Value::Boolean(is_tag));
+            }
+
+            Op::UnpackVariant => {
+                let v = thread.stack.pop()?;
+                for field in &v.as_variant()?.fields {
+                    thread.stack.push(field.clone());
+                }
+            }
+
+            Op::MakeRecord(names) => {
+                let values = thread.stack.pop_n(names.len())?;
+                let mut fields: Vec<(Rc<str>, Value)> = names.iter().cloned().zip(values).collect();
+                fields.sort_by(|(left_name, _), (right_name, _)| left_name.cmp(right_name));
+                thread.stack.push(Value::Record(Rc::new(fields)));
+            }
+
+            Op::GetField(offset) => {
+                let record = thread.stack.pop()?;
+                let (_, value) = &record.as_record()?[*offset];
+                thread.stack.push(value.clone());
+            }
+
+            Op::UpdateRecord(offsets) => {
+                let values = thread.stack.pop_n(offsets.len())?;
+                let record = thread.stack.pop()?;
+                let mut fields = record.as_record()?.to_vec();
+                for (offset, value) in offsets.iter().zip(values) {
+                    fields[*offset].1 = value;
+                }
+                thread.stack.push(Value::Record(Rc::new(fields)));
+            }
+
+            Op::MakeTuple(count) => {
+                let elements = thread.stack.pop_n(*count)?;
+                thread.stack.push(Value::Tuple(Rc::new(elements)));
+            }
+
             Op::MakeProc(start, capture_ops) => {
                 let proc_captures: Vec<Value> = capture_ops
                     .iter()
                     .map(|c| match c {
-                        Capture::Local(stack_offset) => {
-                            stack.value_at(stack_base, *stack_offset).clone()
-                        }
-                        Capture::Capture(CaptureOffset(offset)) => captures[*offset].clone(),
+                        Capture::Local(stack_offset) => thread
+                            .stack
+                            .value_at(thread.stack_base, *stack_offset)
+                            .clone(),
+                        Capture::Capture(CaptureOffset(offset)) => thread.captures[*offset].clone(),
                     })
                     .collect();
                 let proc = Procedure::new(*start, proc_captures);
                 let proc = Rc::new(proc);
                 let value = Value::Procedure(proc);
-                stack.push(value);
+                thread.stack.push(value);
+            }
+
+            Op::Multiply => {
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                let v = Value::Integer(checked(x1.checked_mul(x2))?);
+                thread.stack.push(v);
             }
 
             Op::Negate => {
-                let i = stack.pop_int()?;
-                let v = Value::Integer(-i);
-                stack.push(v);
+                let i = thread.stack.pop_int()?;
+                let v = Value::Integer(checked(i.checked_neg())?);
+                thread.stack.push(v);
+            }
+
+            Op::NewRef => {
+                let v = thread.stack.pop()?;
+                thread.stack.push(Value::Ref(Rc::new(RefCell::new(v))));
+            }
+
+            Op::Not => {
+                let b = thread.stack.pop_bool()?;
+                thread.stack.push(Value::Boolean(!b));
+            }
+
+            Op::NotEqual => {
+                let v2 = thread.stack.pop()?;
+                let v1 = thread.stack.pop()?;
+                let v = Value::Boolean(!v1.equals(&v2)?);
+                thread.stack.push(v);
+            }
+
+            Op::Pop => {
+                thread.stack.pop()?;
             }
 
             Op::PushCapture(CaptureOffset(capture_offset)) => {
-                let v = captures[*capture_offset].clone();
-                stack.push(v);
+                let v = thread.captures[*capture_offset].clone();
+                thread.stack.push(v);
             }
 
             Op::PushGlobal(stack_offset) => {
-                let v = stack.value_at(*stack_offset, StackOffset(0)).clone();
-                stack.push(v);
+                let v = thread.stack.value_at(*stack_offset, StackOffset(0)).clone();
+                thread.stack.push(v);
             }
 
             Op::PushLocal(offset) => {
-                let v = stack.value_at(stack_base, *offset).clone();
-                stack.push(v);
+                let v = thread.stack.value_at(thread.stack_base, *offset).clone();
+                thread.stack.push(v);
             }
 
             Op::PushValue(value) => {
-                stack.push(value.clone());
+                thread.stack.push(value.clone());
+            }
+
+            Op::Remainder => {
+                let x2 = thread.stack.pop_int()?;
+                let x1 = thread.stack.pop_int()?;
+                let v = Value::Integer(checked_division(x1, x2, i64::checked_rem)?);
+                thread.stack.push(v);
             }
 
             Op::Return => {
-                let return_value = stack
-                    .value_at(StackOffset(stack.len() - 1), StackOffset(0))
+                let return_value = thread
+                    .stack
+                    .value_at(StackOffset(thread.stack.len() - 1), StackOffset(0))
                     .clone();
-                stack.pop_to(stack_base)?;
-                stack.push(return_value);
+                thread.stack.pop_to(thread.stack_base)?;
+                thread.stack.push(return_value);
 
-                let Some(frame) = call_stack.pop() else {
+                let Some(frame) = thread.call_stack.pop() else {
                     return Err(String::from("call stack underflow"));
                 };
-                next_op = frame.next_op;
-                stack_base = frame.stack_base;
-                captures = frame.captures;
+                thread.next_op = frame.next_op;
+                thread.stack_base = frame.stack_base;
+                thread.captures = frame.captures;
+            }
+
+            Op::SetRef => {
+                let v = thread.stack.pop()?;
+                let reference = thread.stack.pop()?;
+                *reference.as_ref()?.borrow_mut() = v;
+                thread.stack.push(Value::Unit);
             }
 
-            Op::TailCall => {
-                let argument = stack.pop()?;
-                let proc = stack.pop()?;
+            Op::TailCall(arity) => {
+                let arguments = thread.stack.pop_n(*arity)?;
+                let proc = thread.stack.pop()?;
 
                 // Cleanup stack frame.
-                stack.pop_to(stack_base)?;
+                thread.stack.pop_to(thread.stack_base)?;
 
                 // Set up a jump to procedure.
                 {
                     let p = proc.as_proc()?;
-                    next_op = p.start;
-                    captures = Rc::clone(&p.captures);
+                    thread.next_op = p.start;
+                    thread.captures = Rc::clone(&p.captures);
                 }
 
                 // Setup stack so it looks like the proc was called instead of
                 // jumped to.
-                stack.push(proc);
-                stack.push(argument);
+                thread.stack.push(proc);
+                for argument in arguments {
+                    thread.stack.push(argument);
+                }
+            }
+
+            Op::PushHandler(handler) => {
+                let stack_top = StackOffset(thread.stack.len());
+                let frame = Frame::new_handler(
+                    *handler,
+                    thread.stack_base,
+                    Rc::clone(&thread.captures),
+                    stack_top,
+                );
+                thread.call_stack.push(frame);
+            }
+
+            Op::PopHandler => {
+                thread.call_stack.pop();
+            }
+
+            Op::Raise => {
+                let exception = thread.stack.pop()?;
+                let (frame, stack_top) = loop {
+                    let Some(frame) = thread.call_stack.pop() else {
+                        return Err(format!("uncaught exception {exception}"));
+                    };
+                    if let Some(stack_top) = frame.handler_stack_top {
+                        break (frame, stack_top);
+                    }
+                };
+                thread.stack.pop_to(stack_top)?;
+                thread.stack.push(exception);
+                thread.next_op = frame.next_op;
+                thread.stack_base = frame.stack_base;
+                thread.captures = frame.captures;
+            }
+
+            Op::Unpack(count) => {
+                let tuple = thread.stack.pop()?;
+                let elements = tuple.as_tuple()?;
+                if elements.len() != *count {
+                    return Err(String::from("tuple has the wrong number of elements"));
+                }
+                for element in elements {
+                    thread.stack.push(element.clone());
+                }
+            }
+
+            Op::Spawn => {
+                let proc = thread.stack.pop()?;
+                scheduler.make_ready(thread.spawn(proc, end)?);
+                thread.stack.push(Value::Unit);
+            }
+
+            Op::Yield => {
+                thread.stack.push(Value::Unit);
+                thread = scheduler.switch(thread);
+            }
+
+            Op::NewMutex => {
+                let mutex = Mutex {
+                    is_locked: false,
+                    waiting: VecDeque::new(),
+                };
+                thread
+                    .stack
+                    .push(Value::Mutex(Rc::new(RefCell::new(mutex))));
+            }
+
+            Op::Wait => {
+                let mutex = thread.stack.pop()?;
+                let mut mutex = mutex.as_mutex()?.borrow_mut();
+                thread.stack.push(Value::Unit);
+                if mutex.is_locked {
+                    let Some(next_thread) = scheduler.next() else {
+                        return finish(main_value);
+                    };
+                    mutex
+                        .waiting
+                        .push_back(mem::replace(&mut thread, next_thread));
+                } else {
+                    mutex.is_locked = true;
+                }
+            }
+
+            Op::Signal => {
+                let mutex = thread.stack.pop()?;
+                let mut mutex = mutex.as_mutex()?.borrow_mut();
+                match mutex.waiting.pop_front() {
+                    Some(waiting_thread) => scheduler.make_ready(waiting_thread),
+                    None => mutex.is_locked = false,
+                }
+                thread.stack.push(Value::Unit);
+            }
+
+            Op::MakeClass {
+                name,
+                fields,
+                methods,
+                has_superclass,
+            } => {
+                let procs = thread.stack.pop_n(methods.len())?;
+                let mut class_methods = if *has_superclass {
+                    thread.stack.pop()?.as_class()?.methods.clone()
+                } else {
+                    Vec::new()
+                };
+                for (method_name, proc) in methods.iter().zip(procs) {
+                    match class_methods
+                        .iter_mut()
+                        .find(|(inherited_name, _)| inherited_name == method_name)
+                    {
+                        Some((_, inherited)) => *inherited = proc,
+                        None => class_methods.push((Rc::clone(method_name), proc)),
+                    }
+                }
+                let class = Class {
+                    name: Rc::clone(name),
+                    fields: fields.clone(),
+                    methods: class_methods,
+                };
+                thread.stack.push(Value::Class(Rc::new(class)));
+            }
+
+            Op::NewObject(arity) => {
+                let class = Rc::clone(thread.stack.peek(*arity).as_class()?);
+                let object = Object {
+                    class: Rc::clone(&class),
+                    fields: RefCell::new(vec![None; class.fields.len()]),
+                };
+                let object = Value::Object(Rc::new(object));
+                let class_offset = StackOffset(thread.stack.len() - arity - 1);
+                thread
+                    .stack
+                    .set_value_at(class_offset, StackOffset(0), object.clone());
+                if let Ok(initialize) = class.method("initialize") {
+                    thread.stack.insert_below(*arity, object);
+                    thread.stack.insert_below(*arity + 1, initialize.clone());
+                    thread.call(*arity + 1)?;
+                } else {
+                    thread.stack.push(Value::Unit);
+                }
+            }
+
+            Op::GetObjectField(offset) => {
+                let object = thread.stack.pop()?;
+                let object = object.as_object()?;
+                let Some(v) = object.fields.borrow()[*offset].clone() else {
+                    let name = &object.class.fields[*offset];
+                    return Err(format!("field `{name}` read before it was set"));
+                };
+                thread.stack.push(v);
+            }
+
+            Op::SetObjectField(offset) => {
+                let v = thread.stack.pop()?;
+                let object = thread.stack.pop()?;
+                object.as_object()?.fields.borrow_mut()[*offset] = Some(v);
+                thread.stack.push(Value::Unit);
+            }
+
+            Op::Send { method, arity } => {
+                let object = thread.stack.peek(*arity).as_object()?;
+                let proc = object.class.method(method)?.clone();
+                thread.stack.insert_below(*arity + 1, proc);
+                thread.call(*arity + 1)?;
+            }
+
+            Op::SuperSend { method, arity } => {
+                let class = thread.stack.pop()?;
+                let proc = class.as_class()?.method(method)?.clone();
+                thread.stack.insert_below(*arity + 1, proc);
+                thread.call(*arity + 1)?;
             }
         }
     }
+}
 
-    stack.pop()
+/// Gives the program's value once no thread is ready to run, which is an error
+/// if the main thread has not finished.
+fn finish(main_value: Option<(Value, Vec<Value>)>) -> Result<(Value, Vec<Value>), String> {
+    main_value.ok_or_else(|| String::from("deadlock: every thread is waiting on a mutex"))
+}
+
+/// Turns the result of a checked integer operation into a runtime error on
+/// overflow.
+fn checked(result: Option<i64>) -> Result<i64, String> {
+    result.ok_or_else(|| String::from("integer overflow"))
+}
+
+/// Performs a checked division-like operation, distinguishing division by zero
+/// from overflow.
+fn checked_division(
+    x1: i64,
+    x2: i64,
+    operation: fn(i64, i64) -> Option<i64>,
+) -> Result<i64, String> {
+    if x2 == 0 {
+        Err(String::from("division by zero"))
+    } else {
+        checked(operation(x1, x2))
+    }
 }
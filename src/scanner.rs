@@ -4,61 +4,214 @@ use std::fmt;
 use std::str::Chars;
 
 /// Represents a token's type in a source text.
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum TokenTag {
+    And,
     Arrow,
     Assert,
+    Bar,
+    Begin,
+    Body,
     Bool,
+    CallCc,
+    Car,
+    Catch,
+    Cdr,
+    Class,
     Colon,
     Comma,
+    Concat,
+    Cons,
+    Cont,
+    Data,
+    Def,
+    DefRec,
+    DeRef,
+    Dot,
     Else,
+    EmptyList,
+    End,
     Eof,
     Equal,
+    Extends,
     False,
+    Field,
+    From,
+    Greater,
+    GreaterEqual,
     Identifier(String),
     If,
+    Import,
     In,
+    Interface,
     Int,
+    IntToString,
+    IsNull,
+    LeftBrace,
+    LeftBracket,
     LeftParen,
+    Less,
+    LessEqual,
     Let,
+    Length,
+    LetCc,
     LetRec,
+    ListOf,
+    Match,
+    Method,
+    Module,
+    New,
     Proc,
+    Raise,
+    Ref,
     MinusSign,
+    Mutex,
+    NewRef,
+    Not,
+    NotEqual,
     Number(i64),
+    Opaque,
+    Or,
+    Percent,
+    Plus,
+    RightBrace,
+    RightBracket,
     RightParen,
+    SelfObject,
+    Semicolon,
+    Send,
+    Set,
+    SetRef,
+    Signal,
+    Slash,
+    Spawn,
+    Star,
+    String,
+    StringLiteral(String),
+    StringToInt,
+    Substring,
+    Super,
+    Take,
     Then,
+    Throw,
+    To,
+    Transparent,
     True,
+    Try,
+    Type,
     IsZero,
+    Unit,
+    Unpack,
+    Wait,
+    With,
+    Yield,
 }
 
 impl fmt::Display for TokenTag {
+    #[allow(clippy::too_many_lines)]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let token_str = match self {
+            TokenTag::And => "and",
             TokenTag::Arrow => "->",
             TokenTag::Assert => "assert",
+            TokenTag::Bar => "|",
+            TokenTag::Begin => "begin",
+            TokenTag::Body => "body",
             TokenTag::Bool => "bool",
+            TokenTag::CallCc => "callcc",
+            TokenTag::Car => "car",
+            TokenTag::Catch => "catch",
+            TokenTag::Cdr => "cdr",
+            TokenTag::Class => "class",
             TokenTag::Colon => ":",
             TokenTag::Comma => ",",
+            TokenTag::Concat => "concat",
+            TokenTag::Cons => "cons",
+            TokenTag::Cont => "cont",
+            TokenTag::Data => "data",
+            TokenTag::Def => "def",
+            TokenTag::DefRec => "defrec",
+            TokenTag::DeRef => "deref",
+            TokenTag::Dot => ".",
             TokenTag::Else => "else",
+            TokenTag::EmptyList => "emptylist",
+            TokenTag::End => "end",
             TokenTag::Eof => "EOF",
             TokenTag::Equal => "=",
+            TokenTag::Extends => "extends",
             TokenTag::False => "false",
+            TokenTag::Field => "field",
+            TokenTag::From => "from",
+            TokenTag::Greater => ">",
+            TokenTag::GreaterEqual => ">=",
             TokenTag::Identifier(id) => {
                 return write!(f, "identifier({id})");
             }
             TokenTag::If => "if",
+            TokenTag::Import => "import",
             TokenTag::In => "in",
+            TokenTag::Interface => "interface",
             TokenTag::Int => "int",
+            TokenTag::IntToString => "int_to_string",
+            TokenTag::IsNull => "null?",
+            TokenTag::LeftBrace => "{",
+            TokenTag::LeftBracket => "[",
             TokenTag::LeftParen => "(",
+            TokenTag::Less => "<",
+            TokenTag::LessEqual => "<=",
             TokenTag::Let => "let",
+            TokenTag::Length => "length",
+            TokenTag::LetCc => "letcc",
             TokenTag::LetRec => "letrec",
+            TokenTag::ListOf => "listof",
+            TokenTag::Match => "match",
+            TokenTag::Method => "method",
+            TokenTag::Module => "module",
+            TokenTag::New => "new",
             TokenTag::Proc => "proc",
+            TokenTag::Raise => "raise",
+            TokenTag::Ref => "ref",
             TokenTag::MinusSign => "-",
+            TokenTag::Mutex => "mutex",
+            TokenTag::NewRef => "newref",
+            TokenTag::Not => "not",
+            TokenTag::NotEqual => "!=",
             TokenTag::Number(_) => "number",
+            TokenTag::Opaque => "opaque",
+            TokenTag::Or => "or",
+            TokenTag::Percent => "%",
+            TokenTag::Plus => "+",
+            TokenTag::RightBrace => "}",
+            TokenTag::RightBracket => "]",
             TokenTag::RightParen => ")",
+            TokenTag::SelfObject => "self",
+            TokenTag::Semicolon => ";",
+            TokenTag::Send => "send",
+            TokenTag::Set => "set",
+            TokenTag::SetRef => "setref",
+            TokenTag::Signal => "signal",
+            TokenTag::Slash => "/",
+            TokenTag::Spawn => "spawn",
+            TokenTag::Star => "*",
+            TokenTag::String => "string",
+            TokenTag::StringLiteral(_) => "string literal",
+            TokenTag::StringToInt => "string_to_int",
+            TokenTag::Substring => "substring",
+            TokenTag::Super => "super",
+            TokenTag::Take => "take",
             TokenTag::Then => "then",
+            TokenTag::Throw => "throw",
+            TokenTag::To => "to",
+            TokenTag::Transparent => "transparent",
             TokenTag::True => "true",
+            TokenTag::Try => "try",
+            TokenTag::Type => "type",
             TokenTag::IsZero => "zero?",
+            TokenTag::Unit => "unit",
+            TokenTag::Unpack => "unpack",
+            TokenTag::Wait => "wait",
+            TokenTag::With => "with",
+            TokenTag::Yield => "yield",
         };
         write!(f, "{token_str}")
     }
@@ -80,6 +233,87 @@ impl Token {
     }
 }
 
+/// The keywords, in alphabetical order, each with the tag of its tokens.
+const KEYWORDS: &[(&str, TokenTag)] = &[
+    ("and", TokenTag::And),
+    ("assert", TokenTag::Assert),
+    ("begin", TokenTag::Begin),
+    ("body", TokenTag::Body),
+    ("bool", TokenTag::Bool),
+    ("callcc", TokenTag::CallCc),
+    ("car", TokenTag::Car),
+    ("catch", TokenTag::Catch),
+    ("cdr", TokenTag::Cdr),
+    ("class", TokenTag::Class),
+    ("concat", TokenTag::Concat),
+    ("cons", TokenTag::Cons),
+    ("cont", TokenTag::Cont),
+    ("data", TokenTag::Data),
+    ("def", TokenTag::Def),
+    ("defrec", TokenTag::DefRec),
+    ("deref", TokenTag::DeRef),
+    ("else", TokenTag::Else),
+    ("emptylist", TokenTag::EmptyList),
+    ("end", TokenTag::End),
+    ("extends", TokenTag::Extends),
+    ("false", TokenTag::False),
+    ("field", TokenTag::Field),
+    ("from", TokenTag::From),
+    ("if", TokenTag::If),
+    ("import", TokenTag::Import),
+    ("in", TokenTag::In),
+    ("int", TokenTag::Int),
+    ("int_to_string", TokenTag::IntToString),
+    ("interface", TokenTag::Interface),
+    ("length", TokenTag::Length),
+    ("let", TokenTag::Let),
+    ("letcc", TokenTag::LetCc),
+    ("letrec", TokenTag::LetRec),
+    ("listof", TokenTag::ListOf),
+    ("match", TokenTag::Match),
+    ("method", TokenTag::Method),
+    ("module", TokenTag::Module),
+    ("mutex", TokenTag::Mutex),
+    ("new", TokenTag::New),
+    ("newref", TokenTag::NewRef),
+    ("not", TokenTag::Not),
+    ("null?", TokenTag::IsNull),
+    ("opaque", TokenTag::Opaque),
+    ("or", TokenTag::Or),
+    ("proc", TokenTag::Proc),
+    ("raise", TokenTag::Raise),
+    ("ref", TokenTag::Ref),
+    ("self", TokenTag::SelfObject),
+    ("send", TokenTag::Send),
+    ("set", TokenTag::Set),
+    ("setref", TokenTag::SetRef),
+    ("signal", TokenTag::Signal),
+    ("spawn", TokenTag::Spawn),
+    ("string", TokenTag::String),
+    ("string_to_int", TokenTag::StringToInt),
+    ("substring", TokenTag::Substring),
+    ("super", TokenTag::Super),
+    ("take", TokenTag::Take),
+    ("then", TokenTag::Then),
+    ("throw", TokenTag::Throw),
+    ("to", TokenTag::To),
+    ("transparent", TokenTag::Transparent),
+    ("true", TokenTag::True),
+    ("try", TokenTag::Try),
+    ("type", TokenTag::Type),
+    ("unit", TokenTag::Unit),
+    ("unpack", TokenTag::Unpack),
+    ("wait", TokenTag::Wait),
+    ("with", TokenTag::With),
+    ("yield", TokenTag::Yield),
+    ("zero?", TokenTag::IsZero),
+];
+
+/// Gives the keywords, in alphabetical order.
+pub fn keywords() -> impl Iterator<Item = &'static str> {
+    KEYWORDS.iter().map(|(keyword, _)| *keyword)
+}
+
 /// Represents an object which converts a source text into a stream of tokens.
 pub struct Scanner<'a> {
     chars: Chars<'a>,
@@ -87,10 +321,10 @@ pub struct Scanner<'a> {
     line: usize,
 }
 
-impl<'a> Scanner<'a> {
+impl Scanner<'_> {
     /// Creates a scanner object which is ready to produce tokens from a given
     /// source text.
-    pub fn new(src: &str) -> Scanner {
+    pub fn new(src: &str) -> Scanner<'_> {
         let mut scanner = Scanner {
             chars: src.chars(),
             current: None,
@@ -100,6 +334,11 @@ impl<'a> Scanner<'a> {
         scanner
     }
 
+    /// Tests if the whole source text has been scanned.
+    pub fn is_at_end(&self) -> bool {
+        self.current.is_none()
+    }
+
     fn advance(&mut self) {
         if let Some('\n') = self.current {
             self.line += 1;
@@ -130,44 +369,31 @@ impl<'a> Scanner<'a> {
 
         if self.current.is_none() {
             Ok(Token::new(TokenTag::Eof, self.line))
-        } else if self.current.map_or(false, is_alpha) {
-            self.identifier()
-        } else if self.current.map_or(false, is_digit) {
+        } else if self.current.is_some_and(is_alpha) {
+            Ok(self.identifier())
+        } else if self.current.is_some_and(is_digit) {
             self.number_literal()
+        } else if self.current == Some('"') {
+            self.string_literal()
         } else {
             self.symbol()
         }
     }
 
-    fn identifier(&mut self) -> Result<Token, String> {
+    fn identifier(&mut self) -> Token {
         let line = self.line;
 
         let mut s = String::new();
-        while self
-            .current
-            .map_or(false, |c| is_alpha(c) || is_digit(c) || c == '?')
-        {
+        while self.current.is_some_and(is_identifier_char) {
             self.collect(&mut s);
         }
 
-        let tag = match s.as_ref() {
-            "assert" => TokenTag::Assert,
-            "bool" => TokenTag::Bool,
-            "else" => TokenTag::Else,
-            "false" => TokenTag::False,
-            "if" => TokenTag::If,
-            "in" => TokenTag::In,
-            "int" => TokenTag::Int,
-            "let" => TokenTag::Let,
-            "letrec" => TokenTag::LetRec,
-            "proc" => TokenTag::Proc,
-            "then" => TokenTag::Then,
-            "true" => TokenTag::True,
-            "zero?" => TokenTag::IsZero,
-            _ => TokenTag::Identifier(s),
-        };
+        let tag = KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == s)
+            .map_or(TokenTag::Identifier(s), |(_, tag)| tag.clone());
 
-        return Ok(Token::new(tag, line));
+        Token::new(tag, line)
     }
 
     fn number_literal(&mut self) -> Result<Token, String> {
@@ -175,7 +401,7 @@ impl<'a> Scanner<'a> {
 
         let mut s = String::new();
 
-        while self.current.map_or(false, is_digit) {
+        while self.current.is_some_and(is_digit) {
             self.collect(&mut s);
         }
 
@@ -185,6 +411,40 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    fn string_literal(&mut self) -> Result<Token, String> {
+        let line = self.line;
+
+        // Skip the opening quote.
+        self.advance();
+        let mut s = String::new();
+        loop {
+            match self.current {
+                None => return Err(String::from("unterminated string literal")),
+                Some('"') => break,
+                Some('\\') => {
+                    self.advance();
+                    let c = match self.current {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(c) => return Err(format!("unknown escape sequence '\\{c}'")),
+                        None => return Err(String::from("unterminated string literal")),
+                    };
+                    s.push(c);
+                    self.advance();
+                }
+                Some(_) => self.collect(&mut s),
+            }
+        }
+        // Skip the closing quote.
+        self.advance();
+
+        Ok(Token::new(TokenTag::StringLiteral(s), line))
+    }
+
     fn symbol(&mut self) -> Result<Token, String> {
         let line = self.line;
 
@@ -192,10 +452,31 @@ impl<'a> Scanner<'a> {
         let tag = match self.current.unwrap() {
             '(' => TokenTag::LeftParen,
             ')' => TokenTag::RightParen,
+            '[' => TokenTag::LeftBracket,
+            ']' => TokenTag::RightBracket,
+            '{' => TokenTag::LeftBrace,
+            '}' => TokenTag::RightBrace,
+            '.' => TokenTag::Dot,
             ':' => TokenTag::Colon,
             ',' => TokenTag::Comma,
+            ';' => TokenTag::Semicolon,
+            '|' => TokenTag::Bar,
             '-' => TokenTag::MinusSign,
+            '+' => TokenTag::Plus,
+            '*' => TokenTag::Star,
+            '/' => TokenTag::Slash,
+            '%' => TokenTag::Percent,
             '=' => TokenTag::Equal,
+            '<' => TokenTag::Less,
+            '>' => TokenTag::Greater,
+            '!' => {
+                // `!` is only valid as part of `!=`.
+                self.advance();
+                if self.current != Some('=') {
+                    return Err(String::from("unexpected character '!'"));
+                }
+                TokenTag::NotEqual
+            }
             c => return Err(format!("unexpected character '{c}'")),
         };
 
@@ -208,6 +489,14 @@ impl<'a> Scanner<'a> {
                 self.advance();
                 TokenTag::Arrow
             }
+            Some('=') if tag == TokenTag::Less => {
+                self.advance();
+                TokenTag::LessEqual
+            }
+            Some('=') if tag == TokenTag::Greater => {
+                self.advance();
+                TokenTag::GreaterEqual
+            }
             _ => tag,
         };
 
@@ -228,6 +517,11 @@ fn is_digit(c: char) -> bool {
     c.is_ascii_digit()
 }
 
+/// Tests if a character can be part of an identifier after its first.
+pub fn is_identifier_char(c: char) -> bool {
+    is_alpha(c) || is_digit(c) || c == '?'
+}
+
 fn is_whitespace(c: char) -> bool {
     c == ' ' || c == '\t' || c == '\r' || c == '\n'
 }
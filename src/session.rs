@@ -0,0 +1,132 @@
+//! A session which evaluates programs one after another, as at the REPL. Each
+//! program can use the declarations of the programs evaluated before it.
+
+use std::time::{Duration, Instant};
+
+use crate::ast::{nameless, Program};
+use crate::compiler;
+use crate::name_analysis::{self, Globals};
+use crate::prelude::Prelude;
+use crate::runtime::{self, Op, Value};
+use crate::type_checking::{self, TypeEnv};
+use crate::types::Type;
+
+/// How long each phase of evaluating a program took, in the order they ran.
+pub type Timings = Vec<(&'static str, Duration)>;
+
+/// Runs a phase of evaluating a program, recording how long it took.
+pub fn timed<T>(timings: &mut Timings, phase: &'static str, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = f();
+    timings.push((phase, started.elapsed()));
+    result
+}
+
+pub struct Session {
+    /// The types of the declarations evaluated so far.
+    types: TypeEnv,
+
+    /// Where the globals defined so far are kept.
+    globals: Globals,
+
+    /// The ops of every program run so far. A program's ops are kept even if
+    /// it fails, as it may have stored its procedures in earlier globals.
+    ops: Vec<Op>,
+
+    /// The values of the globals defined so far, which start the main
+    /// thread's stack when the next program runs.
+    stack: Vec<Value>,
+
+    /// The number of ops a thread runs before another thread is run.
+    quantum: usize,
+}
+
+impl Session {
+    /// Creates a session in which the prelude's definitions are in scope.
+    pub fn new(prelude: &Prelude, quantum: usize) -> Result<Self, String> {
+        let mut session = Self {
+            types: TypeEnv::new(&prelude.t_defs),
+            globals: Globals::new(),
+            ops: Vec::new(),
+            stack: Vec::new(),
+            quantum,
+        };
+
+        // The prelude's types are already known, so it only needs to be run.
+        let program = Program {
+            imports: Vec::new(),
+            modules: Vec::new(),
+            classes: Vec::new(),
+            defs: prelude.defs.clone(),
+            expr: None,
+        };
+        session.run(&program, &mut Timings::new())?;
+        Ok(session)
+    }
+
+    /// Gives the names of the values, modules and classes in scope.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.types.names()
+    }
+
+    /// Gives a program's type along with any warnings about it, without
+    /// evaluating it.
+    pub fn type_of(&self, program: &Program) -> Result<(Type, Vec<String>), String> {
+        let mut types = self.types.clone();
+        type_checking::type_of_program(program, &mut types)
+    }
+
+    /// Type checks a program and gives it with its names resolved, as it would
+    /// be run next, without evaluating it.
+    pub fn resolve_names(&self, program: &Program) -> Result<nameless::Program, String> {
+        self.type_of(program)?;
+        let mut globals = self.globals.clone();
+        name_analysis::resolve_names(program, &mut globals)
+    }
+
+    /// Evaluates a program whose imports have been loaded, giving its value
+    /// and type. Any warnings about the program are added to `warnings` before
+    /// it runs, so they are given even if it fails. The program's declarations
+    /// are only kept if it runs without error, though what its type checking
+    /// inferred about earlier declarations is kept once it starts running.
+    pub fn eval(
+        &mut self,
+        program: &Program,
+        timings: &mut Timings,
+        warnings: &mut Vec<String>,
+    ) -> Result<(Value, Type), String> {
+        let mut types = self.types.clone();
+        let (t, program_warnings) = timed(timings, "type checking", || {
+            type_checking::type_of_program(program, &mut types)
+        })?;
+        warnings.extend(program_warnings);
+        match self.run(program, timings) {
+            Ok(value) => {
+                self.types = types;
+                Ok((value, t))
+            }
+            Err(e) => {
+                self.types.keep_inferences(types);
+                Err(e)
+            }
+        }
+    }
+
+    fn run(&mut self, program: &Program, timings: &mut Timings) -> Result<Value, String> {
+        let mut globals = self.globals.clone();
+        let nameless_program = timed(timings, "name analysis", || {
+            name_analysis::resolve_names(program, &mut globals)
+        })?;
+        let start = timed(timings, "compiling", || {
+            compiler::compile(&nameless_program, &mut self.ops)
+        })?;
+        let (value, mut stack) = timed(timings, "running", || {
+            runtime::run(&self.ops, start, self.stack.clone(), self.quantum)
+        })?;
+
+        stack.truncate(nameless_program.global_count);
+        self.globals = globals;
+        self.stack = stack;
+        Ok(value)
+    }
+}
@@ -1,5 +1,6 @@
 //! A table of names and associated values.
 
+#[derive(Clone)]
 pub struct Item<T> {
     pub name: String,
     pub value: T,
@@ -11,6 +12,7 @@ impl<T> Item<T> {
     }
 }
 
+#[derive(Clone)]
 pub struct Table<T> {
     pub items: Vec<Item<T>>,
 }
@@ -1,144 +1,1622 @@
-//! Type checks a letpl program
+//! Type checks a letpl program by inferring the type of each expression.
 
-use crate::ast::{Expr, Program};
+use std::mem;
+
+use crate::ast::{
+    is_assigned_by, Arm, Class, Declaration, Def, Definition, Expr, Method, Module, Param, Program,
+    RecProc, Variant,
+};
 use crate::table::Table;
 use crate::types::Type;
 
-pub fn type_of_program(program: &Program) -> Result<Type, String> {
-    let mut env = Table::new();
-    type_of_expr(&program.expr, &mut env)
+/// Gives the types of the values the prelude defines, in order.
+pub fn type_of_prelude(defs: &[Def]) -> Result<Vec<(String, Type)>, String> {
+    let mut checker = Checker::new();
+    checker.type_of_defs(defs, None)?;
+
+    // A type with type variables which are not quantified would refer to this
+    // inference's substitution, so cannot be used by another.
+    let mut t_definitions = Vec::new();
+    for item in &checker.env.items {
+        let mut ids = Vec::new();
+        checker.free_vars(&item.value, &mut ids);
+        if !ids.is_empty() {
+            let t = checker.resolve(&item.value);
+            return Err(format!(
+                "`{}` has type `{t}` which is not fully known",
+                item.name
+            ));
+        }
+        t_definitions.push((item.name.clone(), checker.resolve(&item.value)));
+    }
+    Ok(t_definitions)
 }
 
-fn type_of_expr(expr: &Expr, env: &mut Table<Type>) -> Result<Type, String> {
-    match expr {
-        Expr::Assert { test, body, .. } => {
-            let t_test = type_of_expr(test, env)?;
-            if !t_test.is_bool() {
-                let msg = format!("assert guard must be type `bool` but got `{t_test}`");
-                return Err(msg);
+/// The declarations in scope at the start of a program and their types, kept so
+/// a program can use the declarations of the programs checked before it in the
+/// same session.
+#[derive(Clone)]
+pub struct TypeEnv(Checker);
+
+impl TypeEnv {
+    /// Creates an environment in which the prelude's values, given with their
+    /// types, are in scope.
+    pub fn new(prelude: &[(String, Type)]) -> Self {
+        let mut checker = Checker::new();
+        for (name, t) in prelude {
+            // The type's quantified variables are renamed to this inference's
+            // own.
+            let t = checker.instantiate(t);
+            let t = checker.generalize(&t);
+            checker.env.push(name.clone(), t);
+        }
+        TypeEnv(checker)
+    }
+
+    /// Gives the names of the values, modules and classes in scope.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let TypeEnv(checker) = self;
+        let values = checker.env.items.iter().map(|item| item.name.as_str());
+        let modules = checker.modules.items.iter().map(|item| item.name.as_str());
+        let classes = checker.classes.items.iter().map(|item| item.name.as_str());
+        values
+            .chain(modules)
+            .chain(classes)
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Keeps what was inferred about the types of this environment's
+    /// declarations while checking a program in a copy of it, but not the
+    /// program's own declarations. A program which fails at runtime may
+    /// already have stored values of the types inferred.
+    pub fn keep_inferences(&mut self, program_env: TypeEnv) {
+        let TypeEnv(checker) = self;
+        let TypeEnv(mut program_checker) = program_env;
+
+        // The program's classes keep their ids, as an inferred type may be one
+        // of them, but go out of scope.
+        for item in &mut program_checker.classes.items[checker.classes.len()..] {
+            item.name.clear();
+        }
+        checker.substitution = program_checker.substitution;
+        checker.equality_vars = program_checker.equality_vars;
+        checker.data_constructors = program_checker.data_constructors;
+        checker.classes = program_checker.classes;
+    }
+}
+
+/// Gives a program's type along with any warnings about the program, and adds
+/// the program's declarations to the environment.
+pub fn type_of_program(
+    program: &Program,
+    env: &mut TypeEnv,
+) -> Result<(Type, Vec<String>), String> {
+    let TypeEnv(checker) = env;
+    checker.type_of_modules(&program.modules)?;
+    checker.type_of_classes(&program.classes)?;
+    checker.type_of_defs(&program.defs, program.expr.as_deref())?;
+    let t_program = match &program.expr {
+        // The main expression's type is polymorphic just as a `let`'s would
+        // be.
+        Some(expr) if is_value(expr) => {
+            let t_expr = checker.type_of_expr(expr)?;
+            checker.generalize(&t_expr)
+        }
+        Some(expr) => checker.type_of_expr(expr)?,
+        None => Type::new_unit(),
+    };
+    let t_program = checker.resolve(&t_program);
+    let t_program = rename_vars(&t_program, &mut Vec::new());
+    Ok((t_program, mem::take(&mut checker.warnings)))
+}
+
+/// Gives a type whose type variables are numbered in the order they appear,
+/// so a type prints the same no matter how it was inferred.
+fn rename_vars(t: &Type, ids: &mut Vec<usize>) -> Type {
+    if let Some((vars, t_body)) = t.as_forall() {
+        let t_body = rename_vars(t_body, ids);
+        let vars = vars
+            .iter()
+            .filter_map(|id| ids.iter().position(|old_id| old_id == id))
+            .collect();
+        Type::new_forall(vars, t_body)
+    } else if let Some(id) = t.as_var() {
+        let new_id = ids
+            .iter()
+            .position(|&old_id| old_id == id)
+            .unwrap_or_else(|| {
+                ids.push(id);
+                ids.len() - 1
+            });
+        Type::new_var(new_id)
+    } else {
+        let components = t
+            .components()
+            .iter()
+            .map(|component| rename_vars(component, ids))
+            .collect();
+        t.with_components(components)
+    }
+}
+
+/// Gives a type with type variables replaced by the types paired with them.
+fn substitute(t: &Type, replacements: &[(usize, Type)]) -> Type {
+    if let Some(id) = t.as_var() {
+        replacements
+            .iter()
+            .find(|(replaced_id, _)| *replaced_id == id)
+            .map_or_else(|| t.clone(), |(_, replacement)| replacement.clone())
+    } else {
+        let components = t
+            .components()
+            .iter()
+            .map(|component| substitute(component, replacements))
+            .collect();
+        t.with_components(components)
+    }
+}
+
+/// Tests if an expression is a syntactic value, one whose evaluation cannot
+/// allocate a reference. Only the types of values which are never assigned are
+/// generalized, otherwise a reference could be used at more than one type.
+fn is_value(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Proc { .. }
+            | Expr::EmptyList
+            | Expr::LiteralBool(_)
+            | Expr::LiteralInt(_)
+            | Expr::LiteralString(_)
+            | Expr::Name(_)
+            | Expr::Qualified { .. }
+    )
+}
+
+/// Gives the type of a class's method, if it has one.
+fn method_type(info: &ClassInfo, name: &str) -> Option<Type> {
+    info.t_methods
+        .iter()
+        .find(|(method_name, _)| method_name == name)
+        .map(|(_, t_method)| t_method.clone())
+}
+
+/// The ways in which two types can fail to unify.
+enum UnifyError {
+    /// The types are built by different type constructors.
+    Mismatch,
+
+    /// A type variable would have to contain itself.
+    Infinite,
+
+    /// A type variable whose values are compared for equality would have to
+    /// be a type whose values cannot be.
+    Equality(Type),
+}
+
+fn describe_unify_error(e: &UnifyError, msg: String) -> String {
+    match e {
+        UnifyError::Mismatch => msg,
+        UnifyError::Infinite => format!("{msg}, which would be an infinite type"),
+        UnifyError::Equality(t) => {
+            format!("{msg}, and values of type `{t}` cannot be compared for equality")
+        }
+    }
+}
+
+/// The state of type inference.
+#[derive(Clone)]
+struct Checker {
+    /// The types of the names in scope.
+    env: Table<Type>,
+
+    /// The type bound to each type variable so far, indexed by the variable's
+    /// id.
+    substitution: Vec<Option<Type>>,
+
+    /// The ids of the type variables whose values are compared for equality,
+    /// which may only be bound to `int`, `bool` or `string`.
+    equality_vars: Vec<usize>,
+
+    /// The variant types in scope.
+    data_types: Table<Type>,
+
+    /// The constructors in scope.
+    constructors: Table<Constructor>,
+
+    /// The names of each variant type's constructors, indexed by the type's
+    /// id.
+    data_constructors: Vec<Vec<String>>,
+
+    /// The types named by the definitions of the module body being checked,
+    /// or by the declarations of its interface.
+    type_definitions: Table<Type>,
+
+    /// The types and values each module declared so far gives outside its
+    /// body.
+    modules: Table<ModuleInfo>,
+
+    /// The classes declared by the program, in the order they are declared,
+    /// so a class's id is its offset.
+    classes: Table<ClassInfo>,
+
+    /// The id of the class whose method is being checked, if any.
+    current_class: Option<usize>,
+
+    /// Problems found which do not stop the program from running.
+    warnings: Vec<String>,
+}
+
+/// The types and values a module's interface declares, as seen outside the
+/// module's body.
+#[derive(Clone)]
+struct ModuleInfo {
+    t_types: Vec<(String, Type)>,
+    t_values: Vec<(String, Type)>,
+}
+
+/// A class's superclass and the types of its objects' fields and methods,
+/// including inherited ones.
+#[derive(Clone)]
+struct ClassInfo {
+    t_class: Type,
+    superclass: Option<usize>,
+    t_fields: Vec<(String, Type)>,
+    t_methods: Vec<(String, Type)>,
+}
+
+/// A constructor of a variant type.
+#[derive(Clone)]
+struct Constructor {
+    t_data: Type,
+    t_fields: Vec<Type>,
+}
+
+impl Checker {
+    fn new() -> Self {
+        Self {
+            env: Table::new(),
+            substitution: Vec::new(),
+            equality_vars: Vec::new(),
+            data_types: Table::new(),
+            constructors: Table::new(),
+            data_constructors: Vec::new(),
+            type_definitions: Table::new(),
+            modules: Table::new(),
+            classes: Table::new(),
+            current_class: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Creates a type variable not yet used in this inference.
+    fn fresh_var(&mut self) -> Type {
+        self.substitution.push(None);
+        Type::new_var(self.substitution.len() - 1)
+    }
+
+    /// Gives a parameter's declared type or a fresh type variable if it has
+    /// none.
+    fn type_of_param(&mut self, param: &Param) -> Result<Type, String> {
+        match &param.t {
+            Some(t) => self.resolve_annotation(t),
+            None => Ok(self.fresh_var()),
+        }
+    }
+
+    /// Gives a type written in the program with its type names replaced by
+    /// the types they refer to.
+    fn resolve_annotation(&self, t: &Type) -> Result<Type, String> {
+        if let Some(name) = t.as_named() {
+            if let Some(t_data) = self.data_types.lookup(name) {
+                Ok(t_data.clone())
+            } else if let Some(t_definition) = self.type_definitions.lookup(name) {
+                Ok(t_definition.clone())
+            } else if let Some(class) = self.classes.lookup(name) {
+                Ok(class.t_class.clone())
+            } else {
+                Err(format!("undefined type `{name}`"))
             }
-            type_of_expr(body, env)
+        } else if let Some((module, name)) = t.as_qualified() {
+            let info = self.module(module)?;
+            info.t_types
+                .iter()
+                .find(|(type_name, _)| type_name == name)
+                .map(|(_, t_type)| t_type.clone())
+                .ok_or_else(|| format!("module `{module}` has no type `{name}`"))
+        } else {
+            let components = t
+                .components()
+                .iter()
+                .map(|component| self.resolve_annotation(component))
+                .collect::<Result<_, _>>()?;
+            Ok(t.with_components(components))
         }
+    }
 
-        Expr::Call { proc, arg } => {
-            let t_proc = type_of_expr(proc, env)?;
-            let Some((t_param, t_body)) = t_proc.as_proc() else {
-                let msg = format!("call expects proc but got `{t_proc}`");
-                return Err(msg);
+    /// Follows a type variable's bindings until reaching an unbound variable
+    /// or a type which is not a variable.
+    fn prune(&self, t: &Type) -> Type {
+        let mut t = t.clone();
+        while let Some(bound) = t.as_var().and_then(|id| self.substitution[id].as_ref()) {
+            t = bound.clone();
+        }
+        t
+    }
+
+    /// Gives a type with all bound type variables substituted away.
+    fn resolve(&self, t: &Type) -> Type {
+        let t = self.prune(t);
+        let components = t
+            .components()
+            .iter()
+            .map(|component| self.resolve(component))
+            .collect();
+        t.with_components(components)
+    }
+
+    /// Gives a type as it is described in a message, with all bound type
+    /// variables substituted away. A variant type hidden by a later `data`
+    /// with the same name is named as an earlier one, so the two can be told
+    /// apart.
+    fn describe(&self, t: &Type) -> Type {
+        let t = self.resolve(t);
+        self.name_hidden_data(&t)
+    }
+
+    fn name_hidden_data(&self, t: &Type) -> Type {
+        if let Some((name, id)) = t.as_data() {
+            let is_hidden = self
+                .data_types
+                .lookup(name)
+                .and_then(Type::as_data)
+                .is_some_and(|(_, visible_id)| visible_id != id);
+            if is_hidden {
+                Type::new_data(format!("earlier {name}"), id)
+            } else {
+                t.clone()
+            }
+        } else {
+            let components = t
+                .components()
+                .iter()
+                .map(|component| self.name_hidden_data(component))
+                .collect();
+            t.with_components(components)
+        }
+    }
+
+    /// Collects the ids of the type variables in a type which are neither bound
+    /// by the substitution nor quantified by a type scheme.
+    fn free_vars(&self, t: &Type, ids: &mut Vec<usize>) {
+        let t = self.prune(t);
+        if let Some(id) = t.as_var() {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        } else if let Some((vars, t_body)) = t.as_forall() {
+            let mut body_ids = Vec::new();
+            self.free_vars(t_body, &mut body_ids);
+            for id in body_ids {
+                if !vars.contains(&id) && !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        } else {
+            for component in t.components() {
+                self.free_vars(&component, ids);
+            }
+        }
+    }
+
+    /// Turns a type into a type scheme quantified over the type variables
+    /// which are free in the type but not in the environment. A type variable
+    /// whose values are compared for equality is not quantified, as its
+    /// instances would not be checked.
+    fn generalize(&self, t: &Type) -> Type {
+        let mut env_ids = Vec::new();
+        for item in &self.env.items {
+            self.free_vars(&item.value, &mut env_ids);
+        }
+        let mut ids = Vec::new();
+        self.free_vars(t, &mut ids);
+        ids.retain(|id| !env_ids.contains(id) && !self.equality_vars.contains(id));
+        if ids.is_empty() {
+            t.clone()
+        } else {
+            Type::new_forall(ids, self.resolve(t))
+        }
+    }
+
+    /// Gives a type scheme's body with its quantified variables replaced by
+    /// fresh type variables. Other types are given unchanged.
+    fn instantiate(&mut self, t: &Type) -> Type {
+        let Some((vars, t_body)) = t.as_forall() else {
+            return t.clone();
+        };
+        let replacements: Vec<(usize, Type)> =
+            vars.iter().map(|&id| (id, self.fresh_var())).collect();
+        substitute(t_body, &replacements)
+    }
+
+    fn occurs(&self, id: usize, t: &Type) -> bool {
+        let t = self.prune(t);
+        if let Some(other_id) = t.as_var() {
+            other_id == id
+        } else {
+            t.components()
+                .iter()
+                .any(|component| self.occurs(id, component))
+        }
+    }
+
+    fn unify(&mut self, left: &Type, right: &Type) -> Result<(), UnifyError> {
+        let left = self.prune(left);
+        let right = self.prune(right);
+        if let Some(id) = left.as_var() {
+            self.bind(id, &right)
+        } else if let Some(id) = right.as_var() {
+            self.bind(id, &left)
+        } else if left.same_constructor(&right) {
+            for (left, right) in left.components().iter().zip(right.components().iter()) {
+                self.unify(left, right)?;
+            }
+            Ok(())
+        } else {
+            Err(UnifyError::Mismatch)
+        }
+    }
+
+    /// Records that a type variable's values are compared for equality.
+    fn compare_for_equality(&mut self, id: usize) {
+        if !self.equality_vars.contains(&id) {
+            self.equality_vars.push(id);
+        }
+    }
+
+    fn bind(&mut self, id: usize, t: &Type) -> Result<(), UnifyError> {
+        if t.as_var() == Some(id) {
+            return Ok(());
+        }
+        if self.occurs(id, t) {
+            return Err(UnifyError::Infinite);
+        }
+        if self.equality_vars.contains(&id) {
+            if let Some(other_id) = t.as_var() {
+                self.compare_for_equality(other_id);
+            } else if !t.is_int() && !t.is_bool() && !t.is_string() {
+                return Err(UnifyError::Equality(self.describe(t)));
+            }
+        }
+        self.substitution[id] = Some(t.clone());
+        Ok(())
+    }
+
+    /// Unifies an expression's actual type with the type it is expected to
+    /// have, describing both types if they do not unify.
+    fn expect(&mut self, what: &str, expected: &Type, actual: &Type) -> Result<(), String> {
+        if self.is_subclass_of(actual, expected) {
+            return Ok(());
+        }
+        self.unify(expected, actual).map_err(|e| {
+            let expected = self.describe(expected);
+            let actual = self.describe(actual);
+            let msg = format!("{what} expects `{expected}` but got `{actual}`");
+            describe_unify_error(&e, msg)
+        })
+    }
+
+    /// Tests if a type is a class which inherits from another type, so its
+    /// objects may be used where the other type's are expected.
+    fn is_subclass_of(&self, t: &Type, t_super: &Type) -> bool {
+        let (Some(mut id), Some(super_id)) =
+            (self.prune(t).as_class(), self.prune(t_super).as_class())
+        else {
+            return false;
+        };
+        while let Some(superclass) = self.classes.items[id].value.superclass {
+            if superclass == super_id {
+                return true;
+            }
+            id = superclass;
+        }
+        false
+    }
+
+    /// Unifies two types which must match, describing both types if they do
+    /// not unify.
+    fn expect_match(&mut self, what: &str, left: &Type, right: &Type) -> Result<(), String> {
+        self.unify(left, right).map_err(|e| {
+            let left = self.describe(left);
+            let right = self.describe(right);
+            let msg = format!("{what} expect matching types but got `{left}` and `{right}`");
+            describe_unify_error(&e, msg)
+        })
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn type_of_expr(&mut self, expr: &Expr) -> Result<Type, String> {
+        match expr {
+            Expr::Assert { test, body, .. } => {
+                let t_test = self.type_of_expr(test)?;
+                self.expect("assert guard", &Type::new_bool(), &t_test)?;
+                self.type_of_expr(body)
+            }
+
+            Expr::Assign { name, expr } => {
+                let Some(t_name) = self.env.lookup(name) else {
+                    let msg = format!("undefined name `{name}`");
+                    return Err(msg);
+                };
+                // Only names which are never assigned are made polymorphic,
+                // but a later program in a session may assign a definition.
+                if t_name.as_forall().is_some() {
+                    return Err(format!("`{name}` is polymorphic so cannot be assigned"));
+                }
+                let t_name = t_name.clone();
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect(&format!("`set {name}`"), &t_name, &t_expr)?;
+                Ok(Type::new_unit())
+            }
+
+            Expr::Begin(exprs) => {
+                let mut t_last = Type::new_unit();
+                for expr in exprs {
+                    t_last = self.type_of_expr(expr)?;
+                }
+                Ok(t_last)
+            }
+
+            Expr::Call { proc, args } => {
+                let t_proc = self.type_of_expr(proc)?;
+                let t_proc = self.prune(&t_proc);
+                if let Some((t_params, t_result)) = t_proc.as_proc() {
+                    if t_params.len() != args.len() {
+                        let msg = format!(
+                            "call expects {} argument(s) but got {}",
+                            t_params.len(),
+                            args.len()
+                        );
+                        return Err(msg);
+                    }
+                    for (t_param, arg) in t_params.iter().zip(args) {
+                        let t_arg = self.type_of_expr(arg)?;
+                        self.expect("call argument", t_param, &t_arg)?;
+                    }
+                    Ok(t_result.clone())
+                } else if t_proc.as_var().is_some() {
+                    let mut t_args = Vec::new();
+                    for arg in args {
+                        t_args.push(self.type_of_expr(arg)?);
+                    }
+                    let t_result = self.fresh_var();
+                    let t_called = Type::new_proc(t_args, t_result.clone());
+                    self.expect("call", &t_called, &t_proc)?;
+                    Ok(t_result)
+                } else {
+                    let msg = format!("call expects proc but got `{t_proc}`");
+                    Err(msg)
+                }
+            }
+
+            Expr::LiteralInt(_) => Ok(Type::new_int()),
+
+            Expr::LiteralString(_) => Ok(Type::new_string()),
+
+            Expr::Concat { left, right } => {
+                let t_left = self.type_of_expr(left)?;
+                self.expect("concat() first argument", &Type::new_string(), &t_left)?;
+                let t_right = self.type_of_expr(right)?;
+                self.expect("concat() second argument", &Type::new_string(), &t_right)?;
+                Ok(Type::new_string())
+            }
+
+            Expr::Length(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("length() argument", &Type::new_string(), &t_expr)?;
+                Ok(Type::new_int())
+            }
+
+            Expr::Substring { string, start, end } => {
+                let t_string = self.type_of_expr(string)?;
+                self.expect("substring() first argument", &Type::new_string(), &t_string)?;
+                let t_start = self.type_of_expr(start)?;
+                self.expect("substring() second argument", &Type::new_int(), &t_start)?;
+                let t_end = self.type_of_expr(end)?;
+                self.expect("substring() third argument", &Type::new_int(), &t_end)?;
+                Ok(Type::new_string())
+            }
+
+            Expr::IntToString(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("int_to_string() argument", &Type::new_int(), &t_expr)?;
+                Ok(Type::new_string())
+            }
+
+            Expr::StringToInt(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("string_to_int() argument", &Type::new_string(), &t_expr)?;
+                Ok(Type::new_int())
+            }
+
+            Expr::Add { left, right } => self.type_of_arithmetic("+", left, right),
+
+            Expr::Subtract { left, right } => self.type_of_arithmetic("-", left, right),
+
+            Expr::Multiply { left, right } => self.type_of_arithmetic("*", left, right),
+
+            Expr::Divide { left, right } => self.type_of_arithmetic("/", left, right),
+
+            Expr::Remainder { left, right } => self.type_of_arithmetic("%", left, right),
+
+            Expr::And { left, right } => self.type_of_logical("and", left, right),
+
+            Expr::Or { left, right } => self.type_of_logical("or", left, right),
+
+            Expr::Not(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("not() argument", &Type::new_bool(), &t_expr)?;
+                Ok(Type::new_bool())
+            }
+
+            Expr::Equal { left, right } => self.type_of_equality("=", left, right),
+
+            Expr::NotEqual { left, right } => self.type_of_equality("!=", left, right),
+
+            Expr::Less { left, right } => self.type_of_comparison("<", left, right),
+
+            Expr::LessEqual { left, right } => self.type_of_comparison("<=", left, right),
+
+            Expr::Greater { left, right } => self.type_of_comparison(">", left, right),
+
+            Expr::GreaterEqual { left, right } => self.type_of_comparison(">=", left, right),
+
+            Expr::If {
+                test,
+                consequent,
+                alternate,
+            } => {
+                let t_test = self.type_of_expr(test)?;
+                self.expect("`if` test", &Type::new_bool(), &t_test)?;
+                let t_consequent = self.type_of_expr(consequent)?;
+                let t_alternate = self.type_of_expr(alternate)?;
+                self.expect_match("`if` branches", &t_consequent, &t_alternate)?;
+                Ok(t_consequent)
+            }
+
+            Expr::IsZero(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("`zero?`", &Type::new_int(), &t_expr)?;
+                Ok(Type::new_bool())
+            }
+
+            Expr::Let { name, expr, body } => {
+                let t_expr = self.type_of_expr(expr)?;
+                let t_expr = if is_value(expr) && !body.assigns(name) {
+                    self.generalize(&t_expr)
+                } else {
+                    t_expr
+                };
+                self.env.push(name.clone(), t_expr);
+                let t_body = self.type_of_expr(body)?;
+                self.env.pop();
+                Ok(t_body)
+            }
+
+            Expr::LiteralBool(_) => Ok(Type::new_bool()),
+
+            Expr::Proc { params, body } => {
+                let t_params: Vec<Type> = params
+                    .iter()
+                    .map(|param| self.type_of_param(param))
+                    .collect::<Result<_, _>>()?;
+                for (param, t_param) in params.iter().zip(&t_params) {
+                    self.env.push(param.name.clone(), t_param.clone());
+                }
+                let t_body = self.type_of_expr(body)?;
+                for _ in params {
+                    self.env.pop();
+                }
+                let t_proc = Type::new_proc(t_params, t_body);
+                Ok(t_proc)
+            }
+
+            Expr::LetRec { procs, body } => self.type_of_let_rec(procs, body),
+
+            Expr::Name(name) => {
+                if let Some(t_name) = self.env.lookup(name) {
+                    let t_name = t_name.clone();
+                    Ok(self.instantiate(&t_name))
+                } else {
+                    let msg = format!("undefined name `{name}`");
+                    Err(msg)
+                }
+            }
+
+            Expr::Qualified { module, name } => {
+                let info = self.module(module)?;
+                let Some((_, t_value)) = info
+                    .t_values
+                    .iter()
+                    .find(|(value_name, _)| value_name == name)
+                else {
+                    return Err(format!("module `{module}` has no value `{name}`"));
+                };
+                let t_value = t_value.clone();
+                Ok(self.instantiate(&t_value))
+            }
+
+            Expr::Tuple(exprs) => {
+                let mut t_elements = Vec::new();
+                for expr in exprs {
+                    t_elements.push(self.type_of_expr(expr)?);
+                }
+                Ok(Type::new_tuple(t_elements))
+            }
+
+            Expr::Record(fields) => {
+                let mut t_fields: Vec<(String, Type)> = Vec::new();
+                for (name, expr) in fields {
+                    if t_fields.iter().any(|(other_name, _)| other_name == name) {
+                        return Err(format!("record has more than one field `{name}`"));
+                    }
+                    let t_expr = self.type_of_expr(expr)?;
+                    t_fields.push((name.clone(), t_expr));
+                }
+                Ok(Type::new_record(t_fields))
+            }
+
+            Expr::Field {
+                record,
+                name,
+                offset,
+            } => {
+                let t_record = self.type_of_expr(record)?;
+                let (field_offset, t_field) = self.field_of(&t_record, name)?;
+                offset.set(field_offset);
+                Ok(t_field)
+            }
+
+            Expr::Update {
+                record,
+                fields,
+                offsets,
+            } => {
+                let t_record = self.type_of_expr(record)?;
+                let mut field_offsets = Vec::new();
+                for (name, expr) in fields {
+                    let (field_offset, t_field) = self.field_of(&t_record, name)?;
+                    if field_offsets.contains(&field_offset) {
+                        return Err(format!("record update changes `{name}` more than once"));
+                    }
+                    field_offsets.push(field_offset);
+                    let t_expr = self.type_of_expr(expr)?;
+                    self.expect(&format!("field `{name}`"), &t_field, &t_expr)?;
+                }
+                *offsets.borrow_mut() = field_offsets;
+                Ok(t_record)
+            }
+
+            Expr::Unpack { names, expr, body } => {
+                let t_expr = self.type_of_expr(expr)?;
+                let t_elements: Vec<Type> = names.iter().map(|_| self.fresh_var()).collect();
+                let t_tuple = Type::new_tuple(t_elements.clone());
+                self.expect("`unpack`", &t_tuple, &t_expr)?;
+                for (name, t_element) in names.iter().zip(t_elements) {
+                    self.env.push(name.clone(), t_element);
+                }
+                let t_body = self.type_of_expr(body)?;
+                for _ in names {
+                    self.env.pop();
+                }
+                Ok(t_body)
+            }
+
+            Expr::NewRef(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                Ok(Type::new_ref(t_expr))
+            }
+
+            Expr::DeRef(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                let t_referent = self.fresh_var();
+                let t_ref = Type::new_ref(t_referent.clone());
+                self.expect("deref() argument", &t_ref, &t_expr)?;
+                Ok(t_referent)
+            }
+
+            Expr::SetRef { reference, value } => {
+                let t_reference = self.type_of_expr(reference)?;
+                let t_referent = self.fresh_var();
+                let t_ref = Type::new_ref(t_referent.clone());
+                self.expect("setref() first argument", &t_ref, &t_reference)?;
+                let t_value = self.type_of_expr(value)?;
+                self.expect("setref() second argument", &t_referent, &t_value)?;
+                Ok(Type::new_unit())
+            }
+
+            Expr::Data {
+                name,
+                variants,
+                body,
+            } => self.type_of_data(name, variants, body),
+
+            Expr::LetCc { name, body } => {
+                let t_letcc = self.fresh_var();
+                self.env.push(name.clone(), Type::new_cont(t_letcc.clone()));
+                let t_body = self.type_of_expr(body)?;
+                self.env.pop();
+                self.expect("`letcc` body", &t_letcc, &t_body)?;
+                Ok(t_letcc)
+            }
+
+            Expr::CallCc(expr) => {
+                let t_callcc = self.fresh_var();
+                let t_cont = Type::new_cont(t_callcc.clone());
+                let t_proc = Type::new_proc(vec![t_cont], t_callcc.clone());
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("callcc() argument", &t_proc, &t_expr)?;
+                Ok(t_callcc)
+            }
+
+            Expr::Throw { value, cont } => {
+                // A continuation never returns to its `throw`, so `throw` can
+                // be used where any type is expected.
+                let t_value = self.type_of_expr(value)?;
+                let t_cont = self.type_of_expr(cont)?;
+                self.expect("`throw`", &Type::new_cont(t_value), &t_cont)?;
+                Ok(self.fresh_var())
+            }
+
+            Expr::SelfObject => match self.current_class {
+                Some(id) => Ok(self.classes.items[id].value.t_class.clone()),
+                None => Err(String::from("`self` used outside a method")),
+            },
+
+            Expr::NewObject { class, args } => {
+                let Some(info) = self.classes.lookup(class) else {
+                    return Err(format!("undefined class `{class}`"));
+                };
+                let t_class = info.t_class.clone();
+                let t_initialize = method_type(info, "initialize");
+                match t_initialize {
+                    Some(t_initialize) => {
+                        self.type_of_method_call(&format!("`new {class}`"), &t_initialize, args)?;
+                    }
+                    None if args.is_empty() => {}
+                    None => {
+                        let msg = format!(
+                            "`new {class}` expects no arguments as `{class}` has no `initialize` method"
+                        );
+                        return Err(msg);
+                    }
+                }
+                Ok(t_class)
+            }
+
+            Expr::Send {
+                object,
+                method,
+                args,
+            } => {
+                let t_object = self.type_of_expr(object)?;
+                let t_object = self.resolve(&t_object);
+                let Some(id) = t_object.as_class() else {
+                    return Err(if t_object.as_var().is_some() {
+                        format!("the class of an object must be known to send it `{method}`")
+                    } else {
+                        format!("`send` expects an object but got `{t_object}`")
+                    });
+                };
+                let t_method = self.method_of(id, method)?;
+                self.type_of_method_call(&format!("`{method}`"), &t_method, args)
+            }
+
+            Expr::Super { method, args } => {
+                let Some(id) = self.current_class else {
+                    return Err(String::from("`super` used outside a method"));
+                };
+                let Some(superclass) = self.classes.items[id].value.superclass else {
+                    return Err(format!("`object` has no method `{method}`"));
+                };
+                let t_method = self.method_of(superclass, method)?;
+                self.type_of_method_call(&format!("`super {method}`"), &t_method, args)
+            }
+
+            Expr::Spawn(expr) => {
+                let t_proc = Type::new_proc(Vec::new(), self.fresh_var());
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("spawn() argument", &t_proc, &t_expr)?;
+                Ok(Type::new_unit())
+            }
+
+            Expr::Yield => Ok(Type::new_unit()),
+
+            Expr::NewMutex => Ok(Type::new_mutex()),
+
+            Expr::Wait(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("wait() argument", &Type::new_mutex(), &t_expr)?;
+                Ok(Type::new_unit())
+            }
+
+            Expr::Signal(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("signal() argument", &Type::new_mutex(), &t_expr)?;
+                Ok(Type::new_unit())
+            }
+
+            Expr::Raise(expr) => {
+                // An exception's value is a string. As `raise` never gives a
+                // value, it can be used where any type is expected.
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("`raise`", &Type::new_string(), &t_expr)?;
+                Ok(self.fresh_var())
+            }
+
+            Expr::Try {
+                body,
+                name,
+                handler,
+            } => {
+                let t_body = self.type_of_expr(body)?;
+                self.env.push(name.clone(), Type::new_string());
+                let t_handler = self.type_of_expr(handler)?;
+                self.env.pop();
+                self.expect_match("`try` body and handler", &t_body, &t_handler)?;
+                Ok(t_body)
+            }
+
+            Expr::Match { expr, arms } => self.type_of_match(expr, arms),
+
+            Expr::EmptyList => Ok(Type::new_list(self.fresh_var())),
+
+            Expr::Cons { head, tail } => {
+                let t_head = self.type_of_expr(head)?;
+                let t_list = Type::new_list(t_head);
+                let t_tail = self.type_of_expr(tail)?;
+                self.expect("cons() second argument", &t_list, &t_tail)?;
+                Ok(t_list)
+            }
+
+            Expr::Car(expr) => {
+                let t_element = self.fresh_var();
+                self.type_of_list_argument("car()", expr, &t_element)?;
+                Ok(t_element)
+            }
+
+            Expr::Cdr(expr) => {
+                let t_element = self.fresh_var();
+                self.type_of_list_argument("cdr()", expr, &t_element)?;
+                Ok(Type::new_list(t_element))
+            }
+
+            Expr::IsNull(expr) => {
+                let t_element = self.fresh_var();
+                self.type_of_list_argument("null?()", expr, &t_element)?;
+                Ok(Type::new_bool())
+            }
+
+            Expr::List(exprs) => {
+                let t_element = self.fresh_var();
+                for expr in exprs {
+                    let t_expr = self.type_of_expr(expr)?;
+                    self.expect("list element", &t_element, &t_expr)?;
+                }
+                Ok(Type::new_list(t_element))
+            }
+
+            Expr::Negate(expr) => {
+                let t_expr = self.type_of_expr(expr)?;
+                self.expect("-() argument", &Type::new_int(), &t_expr)?;
+                Ok(Type::new_int())
+            }
+        }
+    }
+
+    fn type_of_arithmetic(
+        &mut self,
+        operator: &str,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<Type, String> {
+        let t_left = self.type_of_expr(left)?;
+        let what = format!("{operator}() first argument");
+        self.expect(&what, &Type::new_int(), &t_left)?;
+        let t_right = self.type_of_expr(right)?;
+        let what = format!("{operator}() second argument");
+        self.expect(&what, &Type::new_int(), &t_right)?;
+        Ok(Type::new_int())
+    }
+
+    fn type_of_logical(
+        &mut self,
+        operator: &str,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<Type, String> {
+        let t_left = self.type_of_expr(left)?;
+        let what = format!("{operator}() first argument");
+        self.expect(&what, &Type::new_bool(), &t_left)?;
+        let t_right = self.type_of_expr(right)?;
+        let what = format!("{operator}() second argument");
+        self.expect(&what, &Type::new_bool(), &t_right)?;
+        Ok(Type::new_bool())
+    }
+
+    fn type_of_comparison(
+        &mut self,
+        operator: &str,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<Type, String> {
+        self.type_of_arithmetic(operator, left, right)?;
+        Ok(Type::new_bool())
+    }
+
+    fn type_of_let_rec(&mut self, procs: &[RecProc], body: &Expr) -> Result<Type, String> {
+        self.declare_rec_procs("letrec", procs)?;
+        let t_body = self.type_of_expr(body)?;
+        for _ in procs {
+            self.env.pop();
+        }
+        Ok(t_body)
+    }
+
+    /// Checks top-level definitions, each of which stays in scope, followed
+    /// by the main expression, if any.
+    fn type_of_defs(&mut self, defs: &[Def], expr: Option<&Expr>) -> Result<(), String> {
+        for (i, def) in defs.iter().enumerate() {
+            let later = &defs[i + 1..];
+            match def {
+                Def::Value {
+                    name,
+                    expr: def_expr,
+                } => {
+                    let is_assigned = is_assigned_by(later, expr, name);
+                    let t_def = self.type_of_definition(def_expr, is_assigned)?;
+                    self.env.push(name.clone(), t_def);
+                }
+                Def::Rec(procs) => self.declare_rec_procs("defrec", procs)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks procedures defined together by `letrec` or `defrec`, each of
+    /// which is in scope in all of their bodies, then brings them into scope.
+    fn declare_rec_procs(&mut self, what: &str, procs: &[RecProc]) -> Result<(), String> {
+        let mut t_procs = Vec::new();
+        for (i, proc) in procs.iter().enumerate() {
+            if procs[..i].iter().any(|other| other.name == proc.name) {
+                return Err(format!("`{what}` defines `{}` more than once", proc.name));
+            }
+            let t_params: Vec<Type> = proc
+                .params
+                .iter()
+                .map(|param| self.type_of_param(param))
+                .collect::<Result<_, _>>()?;
+            let t_result = match &proc.t_result {
+                Some(t_result) => self.resolve_annotation(t_result)?,
+                None => self.fresh_var(),
             };
-            let t_arg = type_of_expr(arg, env)?;
-            if t_param != &t_arg {
-                let msg = format!("call expect `{t_param}` argument but got `{t_arg}`");
+            t_procs.push(Type::new_proc(t_params, t_result));
+        }
+        for (proc, t_proc) in procs.iter().zip(&t_procs) {
+            self.env.push(proc.name.clone(), t_proc.clone());
+        }
+        for (proc, t_proc) in procs.iter().zip(&t_procs) {
+            let (t_params, t_result) = t_proc.as_proc().expect("procedure has a proc type");
+            for (param, t_param) in proc.params.iter().zip(t_params) {
+                self.env.push(param.name.clone(), t_param.clone());
+            }
+            let t_body = self.type_of_expr(&proc.body)?;
+            self.expect(&format!("`{}` result", proc.name), t_result, &t_body)?;
+            for _ in &proc.params {
+                self.env.pop();
+            }
+        }
+        for _ in procs {
+            self.env.pop();
+        }
+        for (proc, t_proc) in procs.iter().zip(&t_procs) {
+            let t_proc = self.generalize(t_proc);
+            self.env.push(proc.name.clone(), t_proc);
+        }
+        Ok(())
+    }
+
+    fn type_of_data(
+        &mut self,
+        name: &str,
+        variants: &[Variant],
+        body: &Expr,
+    ) -> Result<Type, String> {
+        let mut constructor_names = Vec::new();
+        for variant in variants {
+            if constructor_names.contains(&variant.name) {
+                let msg = format!("`{name}` declares `{}` more than once", variant.name);
                 return Err(msg);
             }
-            Ok(t_body.clone())
+            constructor_names.push(variant.name.clone());
         }
 
-        Expr::LiteralInt(_) => Ok(Type::new_int()),
+        // The type is in scope in its own declaration so it may be recursive.
+        let t_data = Type::new_data(name.to_string(), self.data_constructors.len());
+        self.data_constructors.push(constructor_names);
+        self.data_types.push(name.to_string(), t_data.clone());
+        for variant in variants {
+            let t_fields: Vec<Type> = variant
+                .t_fields
+                .iter()
+                .map(|t_field| self.resolve_annotation(t_field))
+                .collect::<Result<_, _>>()?;
+            let t_constructor = if t_fields.is_empty() {
+                t_data.clone()
+            } else {
+                Type::new_proc(t_fields.clone(), t_data.clone())
+            };
+            self.env.push(variant.name.clone(), t_constructor);
+            let constructor = Constructor {
+                t_data: t_data.clone(),
+                t_fields,
+            };
+            self.constructors.push(variant.name.clone(), constructor);
+        }
+        let t_body = self.type_of_expr(body)?;
+        for _ in variants {
+            self.env.pop();
+            self.constructors.pop();
+        }
+        self.data_types.pop();
+        Ok(t_body)
+    }
 
-        Expr::Subtract { left, right } => {
-            let t_left = type_of_expr(left, env)?;
-            if !t_left.is_int() {
-                let msg = format!("-() first argument expects `int` but got `{t_left}`");
-                return Err(msg);
+    fn type_of_modules(&mut self, modules: &[Module]) -> Result<(), String> {
+        for module in modules {
+            if self.modules.lookup(&module.name).is_some() {
+                return Err(format!(
+                    "module `{}` is declared more than once",
+                    module.name
+                ));
             }
-            let t_right = type_of_expr(right, env)?;
-            if !t_right.is_int() {
-                let msg = format!("-() second argument expects `int` but got `{t_right}`");
-                return Err(msg);
+            self.type_of_module_body(module)?;
+            let info = self.type_of_interface(module)?;
+            self.modules.push(module.name.clone(), info);
+        }
+        Ok(())
+    }
+
+    /// Checks a module's body and that it defines each type and value its
+    /// interface declares. Inside the body, the interface's types are the
+    /// body's definitions of them.
+    fn type_of_module_body(&mut self, module: &Module) -> Result<(), String> {
+        // The body's values are pushed after the prelude's, which they may
+        // shadow.
+        let env_start = self.env.len();
+        let mut type_count = 0;
+        for (i, definition) in module.body.iter().enumerate() {
+            match definition {
+                Definition::Type { name, t } => {
+                    if self.type_definitions.lookup(name).is_some() {
+                        let msg = format!(
+                            "module `{}` defines type `{name}` more than once",
+                            module.name
+                        );
+                        return Err(msg);
+                    }
+                    let t = self.resolve_annotation(t)?;
+                    self.type_definitions.push(name.clone(), t);
+                    type_count += 1;
+                }
+                Definition::Value { name, expr } => {
+                    if self.env.items[env_start..]
+                        .iter()
+                        .any(|item| &item.name == name)
+                    {
+                        let msg =
+                            format!("module `{}` defines `{name}` more than once", module.name);
+                        return Err(msg);
+                    }
+                    let is_assigned = module.body[i + 1..].iter().any(|later| {
+                        matches!(later, Definition::Value { expr, .. } if expr.assigns(name))
+                    });
+                    let t_expr = self.type_of_definition(expr, is_assigned)?;
+                    self.env.push(name.clone(), t_expr);
+                }
             }
-            Ok(Type::new_int())
         }
 
-        Expr::If {
-            test,
-            consequent,
-            alternate,
-        } => {
-            let t_test = type_of_expr(test, env)?;
-            if !t_test.is_bool() {
-                let msg = format!("`if` test expects `bool` but got `{t_test}`");
-                return Err(msg);
+        for declaration in &module.interface {
+            match declaration {
+                Declaration::Opaque(name) => {
+                    self.module_type_definition(module, name)?;
+                }
+                Declaration::Transparent { name, t } => {
+                    let t_definition = self.module_type_definition(module, name)?;
+                    let t = self.resolve_annotation(t)?;
+                    if t != t_definition {
+                        let msg = format!(
+                            "module `{}` interface declares type `{name}` as `{t}` but its body \
+                             defines it as `{t_definition}`",
+                            module.name
+                        );
+                        return Err(msg);
+                    }
+                }
+                Declaration::Value { name, t } => {
+                    let Some(item) = self.env.items[env_start..]
+                        .iter()
+                        .find(|item| &item.name == name)
+                    else {
+                        let msg = format!(
+                            "module `{}` interface declares `{name}` but its body does not \
+                             define it",
+                            module.name
+                        );
+                        return Err(msg);
+                    };
+                    let t_value = item.value.clone();
+                    let t_value = self.instantiate(&t_value);
+                    let t = self.resolve_annotation(t)?;
+                    let what = format!("module `{}` interface value `{name}`", module.name);
+                    self.expect(&what, &t, &t_value)?;
+                }
+            }
+        }
+
+        for _ in 0..type_count {
+            self.type_definitions.pop();
+        }
+        while self.env.len() > env_start {
+            self.env.pop();
+        }
+        Ok(())
+    }
+
+    /// Gives the type of a value defined by a module's body or `def`,
+    /// generalized unless a later definition assigns it.
+    fn type_of_definition(&mut self, expr: &Expr, is_assigned: bool) -> Result<Type, String> {
+        let t_expr = self.type_of_expr(expr)?;
+        if is_value(expr) && !is_assigned {
+            Ok(self.generalize(&t_expr))
+        } else {
+            Ok(t_expr)
+        }
+    }
+
+    /// Gives the body's definition of a type a module's interface declares.
+    fn module_type_definition(&self, module: &Module, name: &str) -> Result<Type, String> {
+        self.type_definitions.lookup(name).cloned().ok_or_else(|| {
+            format!(
+                "module `{}` interface declares type `{name}` but its body does not define it",
+                module.name
+            )
+        })
+    }
+
+    /// Gives the types and values a module's interface declares as seen
+    /// outside its body, where each opaque type is a new type.
+    fn type_of_interface(&mut self, module: &Module) -> Result<ModuleInfo, String> {
+        let mut t_types = Vec::new();
+        let mut t_values: Vec<(String, Type)> = Vec::new();
+        for declaration in &module.interface {
+            match declaration {
+                Declaration::Opaque(name) | Declaration::Transparent { name, .. } => {
+                    if t_types.iter().any(|(other_name, _)| other_name == name) {
+                        let msg = format!(
+                            "module `{}` interface declares type `{name}` more than once",
+                            module.name
+                        );
+                        return Err(msg);
+                    }
+                    let t = match declaration {
+                        Declaration::Transparent { t, .. } => self.resolve_annotation(t)?,
+                        _ => Type::new_qualified(module.name.clone(), name.clone()),
+                    };
+                    self.type_definitions.push(name.clone(), t.clone());
+                    t_types.push((name.clone(), t));
+                }
+                Declaration::Value { name, t } => {
+                    if t_values.iter().any(|(other_name, _)| other_name == name) {
+                        let msg = format!(
+                            "module `{}` interface declares `{name}` more than once",
+                            module.name
+                        );
+                        return Err(msg);
+                    }
+                    t_values.push((name.clone(), self.resolve_annotation(t)?));
+                }
+            }
+        }
+        for _ in &t_types {
+            self.type_definitions.pop();
+        }
+        Ok(ModuleInfo { t_types, t_values })
+    }
+
+    fn module(&self, name: &str) -> Result<&ModuleInfo, String> {
+        self.modules
+            .lookup(name)
+            .ok_or_else(|| format!("undefined module `{name}`"))
+    }
+
+    fn type_of_classes(&mut self, classes: &[Class]) -> Result<(), String> {
+        // Every class is in scope in every class declaration, so classes can
+        // refer to each other's types. The classes of programs checked before
+        // this one keep the ids before this program's.
+        let first_id = self.classes.len();
+        for (i, class) in classes.iter().enumerate() {
+            if class.name == "object" || self.classes.lookup(&class.name).is_some() {
+                return Err(format!("class `{}` is declared more than once", class.name));
+            }
+            let info = ClassInfo {
+                t_class: Type::new_class(class.name.clone(), first_id + i),
+                superclass: None,
+                t_fields: Vec::new(),
+                t_methods: Vec::new(),
+            };
+            self.classes.push(class.name.clone(), info);
+        }
+        for (i, class) in classes.iter().enumerate() {
+            self.declare_class(first_id + i, class)?;
+        }
+        for (i, class) in classes.iter().enumerate() {
+            let id = first_id + i;
+            self.current_class = Some(id);
+            for method in &class.methods {
+                self.type_of_method(id, method)?;
+            }
+        }
+        self.current_class = None;
+        Ok(())
+    }
+
+    /// Finds the types of a class's fields and methods, checking that each
+    /// method it overrides keeps the overridden method's type.
+    fn declare_class(&mut self, id: usize, class: &Class) -> Result<(), String> {
+        let superclass = if class.superclass == "object" {
+            None
+        } else {
+            match self.classes.lookup_offset(&class.superclass) {
+                Some(super_id) if super_id < id => Some(super_id),
+                Some(_) => {
+                    let msg = format!(
+                        "class `{}` must be declared after its superclass `{}`",
+                        class.name, class.superclass
+                    );
+                    return Err(msg);
+                }
+                None => return Err(format!("undefined class `{}`", class.superclass)),
             }
+        };
+        let (mut t_fields, mut t_methods) = match superclass {
+            Some(super_id) => {
+                let info = &self.classes.items[super_id].value;
+                (info.t_fields.clone(), info.t_methods.clone())
+            }
+            None => (Vec::new(), Vec::new()),
+        };
 
-            let t_consequent = type_of_expr(consequent, env)?;
-            let t_alternate = type_of_expr(alternate, env)?;
-            if t_consequent != t_alternate {
+        for (i, (name, t_field)) in class.fields.iter().enumerate() {
+            if class.fields[..i]
+                .iter()
+                .any(|(other_name, _)| other_name == name)
+            {
                 let msg = format!(
-                    "`if` branches expect matching types but got `{t_consequent}` and `{t_alternate}`"
+                    "class `{}` declares field `{name}` more than once",
+                    class.name
                 );
                 return Err(msg);
             }
-
-            Ok(t_consequent)
+            t_fields.push((name.clone(), self.resolve_annotation(t_field)?));
         }
 
-        Expr::IsZero(expr) => {
-            let t_expr = type_of_expr(expr, env)?;
-            if t_expr.is_int() {
-                Ok(Type::new_bool())
-            } else {
-                let msg = format!("`zero?` expects `int` but got `{t_expr}`");
-                Err(msg)
+        for (i, method) in class.methods.iter().enumerate() {
+            let name = &method.name;
+            if class.methods[..i].iter().any(|other| &other.name == name) {
+                let msg = format!(
+                    "class `{}` declares method `{name}` more than once",
+                    class.name
+                );
+                return Err(msg);
+            }
+            let t_params: Vec<Type> = method
+                .params
+                .iter()
+                .map(|param| match &param.t {
+                    Some(t) => self.resolve_annotation(t),
+                    None => Err(format!(
+                        "method `{name}` parameter `{}` needs a type",
+                        param.name
+                    )),
+                })
+                .collect::<Result<_, _>>()?;
+            let t_result = self.resolve_annotation(&method.t_result)?;
+            let t_method = Type::new_proc(t_params, t_result);
+            match t_methods
+                .iter()
+                .position(|(other_name, _)| other_name == name)
+            {
+                Some(offset) => {
+                    // `initialize` is only sent by `new`, so it may differ
+                    // from the superclass's.
+                    if name != "initialize" {
+                        let what = format!("`{}` method `{name}` override", class.name);
+                        let t_overridden = t_methods[offset].1.clone();
+                        self.expect(&what, &t_overridden, &t_method)?;
+                    }
+                    t_methods[offset].1 = t_method;
+                }
+                None => t_methods.push((name.clone(), t_method)),
             }
         }
 
-        Expr::Let { name, expr, body } => {
-            let t_expr = type_of_expr(expr, env)?;
-            env.push(name.clone(), t_expr);
-            let t_body = type_of_expr(body, env)?;
-            env.pop();
-            Ok(t_body)
+        let info = &mut self.classes.items[id].value;
+        info.superclass = superclass;
+        info.t_fields = t_fields;
+        info.t_methods = t_methods;
+        Ok(())
+    }
+
+    /// Checks a method's body, in which the class's fields and the method's
+    /// parameters are in scope.
+    fn type_of_method(&mut self, id: usize, method: &Method) -> Result<(), String> {
+        let t_fields = self.classes.items[id].value.t_fields.clone();
+        let t_method = self.method_of(id, &method.name)?;
+        let (t_params, t_result) = t_method.as_proc().expect("method has a proc type");
+        for (name, t_field) in &t_fields {
+            self.env.push(name.clone(), t_field.clone());
         }
+        for (param, t_param) in method.params.iter().zip(t_params) {
+            self.env.push(param.name.clone(), t_param.clone());
+        }
+        let t_body = self.type_of_expr(&method.body)?;
+        self.expect(
+            &format!("method `{}` result", method.name),
+            t_result,
+            &t_body,
+        )?;
+        for _ in 0..t_fields.len() + method.params.len() {
+            self.env.pop();
+        }
+        Ok(())
+    }
 
-        Expr::LiteralBool(_) => Ok(Type::new_bool()),
+    /// Gives the type of a class's method, which may be inherited.
+    fn method_of(&self, id: usize, name: &str) -> Result<Type, String> {
+        let info = &self.classes.items[id].value;
+        method_type(info, name).ok_or_else(|| format!("`{}` has no method `{name}`", info.t_class))
+    }
 
-        Expr::Proc { param, body } => {
-            env.push(param.name.clone(), param.t.clone());
-            let t_body = type_of_expr(body, env)?;
-            env.pop();
-            let t_proc = Type::new_proc(param.t.clone(), t_body);
-            Ok(t_proc)
+    /// Checks the arguments of a call to a method with a given type, giving
+    /// the method's result type.
+    fn type_of_method_call(
+        &mut self,
+        what: &str,
+        t_method: &Type,
+        args: &[Expr],
+    ) -> Result<Type, String> {
+        let (t_params, t_result) = t_method.as_proc().expect("method has a proc type");
+        if t_params.len() != args.len() {
+            let msg = format!(
+                "{what} expects {} argument(s) but got {}",
+                t_params.len(),
+                args.len()
+            );
+            return Err(msg);
         }
+        for (t_param, arg) in t_params.iter().zip(args) {
+            let t_arg = self.type_of_expr(arg)?;
+            self.expect(&format!("{what} argument"), t_param, &t_arg)?;
+        }
+        Ok(t_result.clone())
+    }
 
-        Expr::LetRec {
-            t_result,
-            name,
-            param,
-            proc_body,
-            let_body,
-        } => {
-            let t_proc = Type::new_proc(param.t.clone(), t_result.clone());
-            env.push(name.clone(), t_proc);
-            env.push(param.name.clone(), param.t.clone());
-            let t_body = type_of_expr(proc_body, env)?;
-            if t_body != *t_result {
-                let msg =
-                    format!("`{name}` expect result of type `{t_result}` but got `{t_body}`.");
+    fn type_of_match(&mut self, expr: &Expr, arms: &[Arm]) -> Result<Type, String> {
+        let t_expr = self.type_of_expr(expr)?;
+        let t_result = self.fresh_var();
+        let mut matched: Vec<&str> = Vec::new();
+        for arm in arms {
+            let name = &arm.constructor;
+            let Some(constructor) = self.constructors.lookup(name).cloned() else {
+                return Err(format!("undefined constructor `{name}`"));
+            };
+            if matched.contains(&name.as_str()) {
+                return Err(format!("`match` has more than one arm for `{name}`"));
+            }
+            matched.push(name);
+            self.expect("`match`", &constructor.t_data, &t_expr)?;
+            if arm.names.len() != constructor.t_fields.len() {
+                let msg = format!(
+                    "`{name}` pattern expects {} name(s) but got {}",
+                    constructor.t_fields.len(),
+                    arm.names.len()
+                );
                 return Err(msg);
             }
-            env.pop();
-            let t_let_body = type_of_expr(let_body, env)?;
-            env.pop();
-            Ok(t_let_body)
+            for (field_name, t_field) in arm.names.iter().zip(constructor.t_fields) {
+                self.env.push(field_name.clone(), t_field);
+            }
+            let t_body = self.type_of_expr(&arm.body)?;
+            for _ in &arm.names {
+                self.env.pop();
+            }
+            self.expect_match("`match` arms", &t_result, &t_body)?;
         }
 
-        Expr::Name(name) => {
-            if let Some(t_name) = env.lookup(name) {
-                Ok(t_name.clone())
-            } else {
-                let msg = format!("undefined name `{name}`");
-                Err(msg)
+        let t_data = self.prune(&t_expr);
+        if let Some((_, id)) = t_data.as_data() {
+            let missing: Vec<String> = self.data_constructors[id]
+                .iter()
+                .filter(|name| !matched.contains(&name.as_str()))
+                .map(|name| format!("`{name}`"))
+                .collect();
+            if !missing.is_empty() {
+                let warning = format!(
+                    "`match` on `{t_data}` has no arm for {}",
+                    missing.join(", ")
+                );
+                self.warnings.push(warning);
             }
         }
+        Ok(t_result)
+    }
 
-        Expr::Negate(expr) => {
-            let t_expr = type_of_expr(expr, env)?;
-            if !t_expr.is_int() {
-                let msg = format!("-() argument expects `int` but got `{t_expr}`");
-                return Err(msg);
-            }
-            Ok(Type::new_int())
+    /// Gives the offset and type of a record's field. The record's type must
+    /// already be known, as a field name alone does not say which record type
+    /// it belongs to.
+    fn field_of(&self, t_record: &Type, name: &str) -> Result<(usize, Type), String> {
+        let t_record = self.resolve(t_record);
+        if let Some(t_fields) = t_record.as_record() {
+            t_fields
+                .iter()
+                .position(|(field_name, _)| field_name == name)
+                .map(|offset| (offset, t_fields[offset].1.clone()))
+                .ok_or_else(|| format!("`{t_record}` has no field `{name}`"))
+        } else if t_record.as_var().is_some() {
+            Err(format!(
+                "the type of a record must be known to use its field `{name}`"
+            ))
+        } else {
+            Err(format!(
+                "field `{name}` expects a record but got `{t_record}`"
+            ))
+        }
+    }
+
+    /// Checks that an expression is a list of a given element type.
+    fn type_of_list_argument(
+        &mut self,
+        operator: &str,
+        expr: &Expr,
+        t_element: &Type,
+    ) -> Result<(), String> {
+        let t_expr = self.type_of_expr(expr)?;
+        let t_list = Type::new_list(t_element.clone());
+        self.expect(&format!("{operator} argument"), &t_list, &t_expr)
+    }
+
+    fn type_of_equality(
+        &mut self,
+        operator: &str,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<Type, String> {
+        let t_left = self.type_of_expr(left)?;
+        let t_right = self.type_of_expr(right)?;
+        self.expect_match(&format!("{operator}() arguments"), &t_left, &t_right)?;
+
+        // An argument whose type is still unknown must turn out to be one
+        // whose values can be compared.
+        let t_left = self.prune(&t_left);
+        if let Some(id) = t_left.as_var() {
+            self.compare_for_equality(id);
+        } else if !t_left.is_int() && !t_left.is_bool() && !t_left.is_string() {
+            let msg = format!(
+                "{operator}() expects `int`, `bool` or `string` arguments but got `{t_left}`"
+            );
+            return Err(msg);
         }
+        Ok(Type::new_bool())
     }
 }
@@ -6,49 +6,129 @@ use std::rc::Rc;
 enum TypeTag {
     Int,
     Bool,
-    Proc { t_param: Type, t_result: Type },
+    Unit,
+    String,
+    Mutex,
+    Proc { t_params: Vec<Type>, t_result: Type },
+    Ref(Type),
+    List(Type),
+    Cont(Type),
+    Tuple(Vec<Type>),
+    Record(Vec<(String, Type)>),
+    Data { name: String, id: usize },
+    Class { name: String, id: usize },
+    Named(String),
+    Qualified { module: String, name: String },
+    Var(usize),
+    Forall { vars: Vec<usize>, t_body: Type },
 }
 
 impl TypeTag {
     pub fn is_int(&self) -> bool {
+        matches!(self, TypeTag::Int)
+    }
+
+    pub fn is_bool(&self) -> bool {
+        matches!(self, TypeTag::Bool)
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, TypeTag::String)
+    }
+
+    pub fn as_proc(&self) -> Option<(&[Type], &Type)> {
         match self {
-            TypeTag::Int => true,
-            _ => false,
+            TypeTag::Proc { t_params, t_result } => Some((t_params, t_result)),
+            _ => None,
         }
     }
 
-    pub fn is_bool(&self) -> bool {
+    pub fn as_var(&self) -> Option<usize> {
         match self {
-            TypeTag::Bool => true,
-            _ => false,
+            TypeTag::Var(id) => Some(*id),
+            _ => None,
         }
     }
 
-    pub fn as_proc(&self) -> Option<(&Type, &Type)> {
+    pub fn as_record(&self) -> Option<&[(String, Type)]> {
         match self {
-            TypeTag::Proc { t_param, t_result } => Some((t_param, t_result)),
+            TypeTag::Record(t_fields) => Some(t_fields),
             _ => None,
         }
     }
-}
 
-impl PartialEq for TypeTag {
-    fn eq(&self, other: &Self) -> bool {
-        if self.is_int() && other.is_int() {
-            return true;
+    pub fn as_data(&self) -> Option<(&str, usize)> {
+        match self {
+            TypeTag::Data { name, id } => Some((name, *id)),
+            _ => None,
         }
+    }
 
-        if self.is_bool() && other.is_bool() {
-            return true;
+    pub fn as_class(&self) -> Option<usize> {
+        match self {
+            TypeTag::Class { id, .. } => Some(*id),
+            _ => None,
         }
+    }
 
-        if let Some(left_proc) = self.as_proc() {
-            if let Some(right_proc) = other.as_proc() {
-                return left_proc == right_proc;
-            }
+    pub fn as_named(&self) -> Option<&str> {
+        match self {
+            TypeTag::Named(name) => Some(name),
+            _ => None,
         }
+    }
+
+    pub fn as_qualified(&self) -> Option<(&str, &str)> {
+        match self {
+            TypeTag::Qualified { module, name } => Some((module, name)),
+            _ => None,
+        }
+    }
+
+    pub fn as_forall(&self) -> Option<(&[usize], &Type)> {
+        match self {
+            TypeTag::Forall { vars, t_body } => Some((vars, t_body)),
+            _ => None,
+        }
+    }
+}
 
-        return false;
+impl PartialEq for TypeTag {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TypeTag::Int, TypeTag::Int)
+            | (TypeTag::Bool, TypeTag::Bool)
+            | (TypeTag::Unit, TypeTag::Unit)
+            | (TypeTag::String, TypeTag::String)
+            | (TypeTag::Mutex, TypeTag::Mutex) => true,
+            (TypeTag::Var(left_id), TypeTag::Var(right_id))
+            | (TypeTag::Data { id: left_id, .. }, TypeTag::Data { id: right_id, .. })
+            | (TypeTag::Class { id: left_id, .. }, TypeTag::Class { id: right_id, .. }) => {
+                left_id == right_id
+            }
+            (TypeTag::Named(left_name), TypeTag::Named(right_name)) => left_name == right_name,
+            (TypeTag::Qualified { .. }, TypeTag::Qualified { .. }) => {
+                self.as_qualified() == other.as_qualified()
+            }
+            (TypeTag::Proc { .. }, TypeTag::Proc { .. }) => self.as_proc() == other.as_proc(),
+            (TypeTag::Ref(left_referent), TypeTag::Ref(right_referent)) => {
+                left_referent == right_referent
+            }
+            (TypeTag::List(left_element), TypeTag::List(right_element)) => {
+                left_element == right_element
+            }
+            (TypeTag::Cont(left_value), TypeTag::Cont(right_value)) => left_value == right_value,
+            (TypeTag::Tuple(left_elements), TypeTag::Tuple(right_elements)) => {
+                left_elements == right_elements
+            }
+            (TypeTag::Record(left_fields), TypeTag::Record(right_fields)) => {
+                left_fields == right_fields
+            }
+            (TypeTag::Forall { .. }, TypeTag::Forall { .. }) => {
+                self.as_forall() == other.as_forall()
+            }
+            _ => false,
+        }
     }
 }
 
@@ -57,11 +137,74 @@ impl fmt::Display for TypeTag {
         match self {
             TypeTag::Int => write!(f, "int"),
             TypeTag::Bool => write!(f, "bool"),
-            TypeTag::Proc { t_param, t_result } => write!(f, "({t_param} -> {t_result})"),
+            TypeTag::Unit => write!(f, "unit"),
+            TypeTag::String => write!(f, "string"),
+            TypeTag::Mutex => write!(f, "mutex"),
+            TypeTag::Proc { t_params, t_result } => {
+                write!(f, "(")?;
+                for (i, t_param) in t_params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{t_param}")?;
+                }
+                if !t_params.is_empty() {
+                    write!(f, " ")?;
+                }
+                write!(f, "-> {t_result})")
+            }
+            TypeTag::Ref(t_referent) => write!(f, "ref {t_referent}"),
+            TypeTag::List(t_element) => write!(f, "listof {t_element}"),
+            TypeTag::Cont(t_value) => write!(f, "cont {t_value}"),
+            TypeTag::Tuple(t_elements) => {
+                write!(f, "(")?;
+                for (i, t_element) in t_elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " * ")?;
+                    }
+                    write!(f, "{t_element}")?;
+                }
+                write!(f, ")")
+            }
+            TypeTag::Record(t_fields) => {
+                write!(f, "{{")?;
+                for (i, (name, t_field)) in t_fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{name}: {t_field}")?;
+                }
+                write!(f, "}}")
+            }
+            TypeTag::Data { name, .. } | TypeTag::Class { name, .. } | TypeTag::Named(name) => {
+                write!(f, "{name}")
+            }
+            TypeTag::Qualified { module, name } => write!(f, "from {module} take {name}"),
+            TypeTag::Var(id) => write_var_name(f, *id),
+            TypeTag::Forall { vars, t_body } => {
+                write!(f, "forall")?;
+                for id in vars {
+                    write!(f, " ")?;
+                    write_var_name(f, *id)?;
+                }
+                write!(f, ". {t_body}")
+            }
         }
     }
 }
 
+/// Writes a type variable's name, that is `a` through `z`, then `a1` through
+/// `z1`, and so on.
+fn write_var_name(f: &mut fmt::Formatter<'_>, id: usize) -> fmt::Result {
+    let letter = char::from(b'a' + u8::try_from(id % 26).expect("id % 26 fits in a u8"));
+    let round = id / 26;
+    if round == 0 {
+        write!(f, "{letter}")
+    } else {
+        write!(f, "{letter}{round}")
+    }
+}
+
 pub struct Type {
     tag: Rc<TypeTag>,
 }
@@ -77,8 +220,98 @@ impl Type {
         Self { tag }
     }
 
-    pub fn new_proc(t_param: Type, t_result: Type) -> Self {
-        let tag = Rc::new(TypeTag::Proc { t_param, t_result });
+    pub fn new_unit() -> Self {
+        let tag = Rc::new(TypeTag::Unit);
+        Self { tag }
+    }
+
+    pub fn new_string() -> Self {
+        let tag = Rc::new(TypeTag::String);
+        Self { tag }
+    }
+
+    pub fn new_mutex() -> Self {
+        let tag = Rc::new(TypeTag::Mutex);
+        Self { tag }
+    }
+
+    /// Creates the type of a reference to a value of a given type.
+    pub fn new_ref(t_referent: Type) -> Self {
+        let tag = Rc::new(TypeTag::Ref(t_referent));
+        Self { tag }
+    }
+
+    /// Creates the type of a list whose elements have a given type.
+    pub fn new_list(t_element: Type) -> Self {
+        let tag = Rc::new(TypeTag::List(t_element));
+        Self { tag }
+    }
+
+    /// Creates the type of a continuation which is resumed with a value of a
+    /// given type.
+    pub fn new_cont(t_value: Type) -> Self {
+        let tag = Rc::new(TypeTag::Cont(t_value));
+        Self { tag }
+    }
+
+    /// Creates the type of a tuple whose elements have given types.
+    pub fn new_tuple(t_elements: Vec<Type>) -> Self {
+        let tag = Rc::new(TypeTag::Tuple(t_elements));
+        Self { tag }
+    }
+
+    pub fn new_proc(t_params: Vec<Type>, t_result: Type) -> Self {
+        let tag = Rc::new(TypeTag::Proc { t_params, t_result });
+        Self { tag }
+    }
+
+    /// Creates the type of a record whose fields have given names and types.
+    /// The fields are kept sorted by name, which gives each field its offset
+    /// in the record.
+    pub fn new_record(mut t_fields: Vec<(String, Type)>) -> Self {
+        t_fields.sort_by(|(left_name, _), (right_name, _)| left_name.cmp(right_name));
+        let tag = Rc::new(TypeTag::Record(t_fields));
+        Self { tag }
+    }
+
+    /// Creates a variant type declared by `data`. Each declaration has its own
+    /// id, so two declarations with the same name are different types.
+    pub fn new_data(name: String, id: usize) -> Self {
+        let tag = Rc::new(TypeTag::Data { name, id });
+        Self { tag }
+    }
+
+    /// Creates the type of the objects of a class. Each class has its own id.
+    pub fn new_class(name: String, id: usize) -> Self {
+        let tag = Rc::new(TypeTag::Class { name, id });
+        Self { tag }
+    }
+
+    /// Creates a type written by name in a program, which is replaced by the
+    /// type the name refers to when the program is type checked.
+    pub fn new_named(name: String) -> Self {
+        let tag = Rc::new(TypeTag::Named(name));
+        Self { tag }
+    }
+
+    /// Creates a type written as `from m take t` in a program. It is also the
+    /// type an opaque type in a module's interface gives outside the module's
+    /// body, which is distinct from every other type.
+    pub fn new_qualified(module: String, name: String) -> Self {
+        let tag = Rc::new(TypeTag::Qualified { module, name });
+        Self { tag }
+    }
+
+    /// Creates a type variable, a placeholder for a type yet to be inferred.
+    pub fn new_var(id: usize) -> Self {
+        let tag = Rc::new(TypeTag::Var(id));
+        Self { tag }
+    }
+
+    /// Creates a type scheme, a type which may be used at many types by
+    /// substituting types for its quantified variables.
+    pub fn new_forall(vars: Vec<usize>, t_body: Type) -> Self {
+        let tag = Rc::new(TypeTag::Forall { vars, t_body });
         Self { tag }
     }
 
@@ -90,9 +323,161 @@ impl Type {
         self.tag.is_bool()
     }
 
-    pub fn as_proc(&self) -> Option<(&Type, &Type)> {
+    pub fn is_string(&self) -> bool {
+        self.tag.is_string()
+    }
+
+    pub fn as_proc(&self) -> Option<(&[Type], &Type)> {
         self.tag.as_proc()
     }
+
+    pub fn as_var(&self) -> Option<usize> {
+        self.tag.as_var()
+    }
+
+    pub fn as_forall(&self) -> Option<(&[usize], &Type)> {
+        self.tag.as_forall()
+    }
+
+    pub fn as_record(&self) -> Option<&[(String, Type)]> {
+        self.tag.as_record()
+    }
+
+    pub fn as_data(&self) -> Option<(&str, usize)> {
+        self.tag.as_data()
+    }
+
+    pub fn as_class(&self) -> Option<usize> {
+        self.tag.as_class()
+    }
+
+    pub fn as_named(&self) -> Option<&str> {
+        self.tag.as_named()
+    }
+
+    pub fn as_qualified(&self) -> Option<(&str, &str)> {
+        self.tag.as_qualified()
+    }
+
+    /// Gives the types from which this type is directly built.
+    pub fn components(&self) -> Vec<Type> {
+        match self.tag.as_ref() {
+            TypeTag::Int
+            | TypeTag::Bool
+            | TypeTag::Unit
+            | TypeTag::String
+            | TypeTag::Mutex
+            | TypeTag::Data { .. }
+            | TypeTag::Class { .. }
+            | TypeTag::Named(_)
+            | TypeTag::Qualified { .. }
+            | TypeTag::Var(_) => Vec::new(),
+            TypeTag::Proc { t_params, t_result } => {
+                let mut components = t_params.clone();
+                components.push(t_result.clone());
+                components
+            }
+            TypeTag::Ref(t_component) | TypeTag::List(t_component) | TypeTag::Cont(t_component) => {
+                vec![t_component.clone()]
+            }
+            TypeTag::Tuple(t_elements) => t_elements.clone(),
+            TypeTag::Record(t_fields) => t_fields.iter().map(|(_, t)| t.clone()).collect(),
+            TypeTag::Forall { t_body, .. } => vec![t_body.clone()],
+        }
+    }
+
+    /// Creates a type like this one but built from other components. The
+    /// components must be in the order given by `components`.
+    pub fn with_components(&self, mut components: Vec<Type>) -> Type {
+        match self.tag.as_ref() {
+            TypeTag::Int
+            | TypeTag::Bool
+            | TypeTag::Unit
+            | TypeTag::String
+            | TypeTag::Mutex
+            | TypeTag::Data { .. }
+            | TypeTag::Class { .. }
+            | TypeTag::Named(_)
+            | TypeTag::Qualified { .. }
+            | TypeTag::Var(_) => self.clone(),
+            TypeTag::Proc { .. } => {
+                let t_result = components.pop().expect("proc type has a result");
+                Type::new_proc(components, t_result)
+            }
+            TypeTag::Ref(_) => {
+                let t_referent = components.pop().expect("ref type has a referent");
+                Type::new_ref(t_referent)
+            }
+            TypeTag::List(_) => {
+                let t_element = components.pop().expect("list type has an element type");
+                Type::new_list(t_element)
+            }
+            TypeTag::Cont(_) => {
+                let t_value = components
+                    .pop()
+                    .expect("continuation type has a value type");
+                Type::new_cont(t_value)
+            }
+            TypeTag::Tuple(_) => Type::new_tuple(components),
+            TypeTag::Record(t_fields) => {
+                let t_fields = t_fields
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .zip(components)
+                    .collect();
+                Type::new_record(t_fields)
+            }
+            TypeTag::Forall { vars, .. } => {
+                let t_body = components.pop().expect("type scheme has a body");
+                Type::new_forall(vars.clone(), t_body)
+            }
+        }
+    }
+
+    /// Tests if two types are built by the same type constructor, ignoring
+    /// their components.
+    pub fn same_constructor(&self, other: &Type) -> bool {
+        match (self.tag.as_ref(), other.tag.as_ref()) {
+            (TypeTag::Int, TypeTag::Int)
+            | (TypeTag::Bool, TypeTag::Bool)
+            | (TypeTag::Unit, TypeTag::Unit)
+            | (TypeTag::String, TypeTag::String)
+            | (TypeTag::Mutex, TypeTag::Mutex)
+            | (TypeTag::Ref(_), TypeTag::Ref(_))
+            | (TypeTag::List(_), TypeTag::List(_))
+            | (TypeTag::Cont(_), TypeTag::Cont(_)) => true,
+            (TypeTag::Var(left_id), TypeTag::Var(right_id))
+            | (TypeTag::Data { id: left_id, .. }, TypeTag::Data { id: right_id, .. })
+            | (TypeTag::Class { id: left_id, .. }, TypeTag::Class { id: right_id, .. }) => {
+                left_id == right_id
+            }
+            (TypeTag::Named(left_name), TypeTag::Named(right_name)) => left_name == right_name,
+            (TypeTag::Qualified { .. }, TypeTag::Qualified { .. }) => {
+                self.as_qualified() == other.as_qualified()
+            }
+            (
+                TypeTag::Proc {
+                    t_params: left_params,
+                    ..
+                },
+                TypeTag::Proc {
+                    t_params: right_params,
+                    ..
+                },
+            ) => left_params.len() == right_params.len(),
+            (TypeTag::Tuple(left_elements), TypeTag::Tuple(right_elements)) => {
+                left_elements.len() == right_elements.len()
+            }
+            (TypeTag::Record(left_fields), TypeTag::Record(right_fields)) => {
+                left_fields.len() == right_fields.len()
+                    && left_fields
+                        .iter()
+                        .zip(right_fields)
+                        .all(|((left_name, _), (right_name, _))| left_name == right_name)
+            }
+            _ => false,
+        }
+    }
 }
 
 impl PartialEq for Type {
@@ -113,3 +498,9 @@ impl fmt::Display for Type {
         write!(f, "{}", self.tag)
     }
 }
+
+impl fmt::Debug for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}